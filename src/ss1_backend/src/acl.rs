@@ -0,0 +1,170 @@
+// Role wywołujących. Controllerzy canistra mają wszystkie uprawnienia i nie są
+// tu zapisywani; writerów i readerów nadają controllerzy przez grant_role.
+// Reader widzi wartości (ID) w list_entries, writer może też zmieniać drzewo.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::error::ApiError;
+
+thread_local! {
+    static ROLES: RefCell<BTreeMap<Principal, Role>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+// Kolejność ma znaczenie: każda rola obejmuje te przed nią
+#[derive(
+    Serialize, Deserialize, CandidType, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Role {
+    Reader,
+    Writer,
+    Controller,
+}
+
+#[derive(Serialize, Deserialize, CandidType, Clone)]
+pub struct RoleAssignment {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+pub fn role_of(principal: &Principal) -> Option<Role> {
+    if ic_cdk::api::is_controller(principal) {
+        return Some(Role::Controller);
+    }
+    granted_role(principal)
+}
+
+// Rola nadana przez grant_role, bez sprawdzania controllerów
+fn granted_role(principal: &Principal) -> Option<Role> {
+    ROLES.with(|r| r.borrow().get(principal).copied())
+}
+
+// Sprawdza wywołującego; błąd wraca do klienta zamiast trapa
+pub fn require(required: Role) -> Result<(), ApiError> {
+    check(role_of(&ic_cdk::caller()), required)
+}
+
+fn check(role: Option<Role>, required: Role) -> Result<(), ApiError> {
+    match role {
+        Some(role) if role >= required => Ok(()),
+        _ => Err(ApiError::Unauthorized { required }),
+    }
+}
+
+pub fn grant(principal: Principal, role: Role) -> Result<(), ApiError> {
+    if role == Role::Controller {
+        return Err(ApiError::InvalidArgument(
+            "controllers are managed through the canister settings".to_string(),
+        ));
+    }
+    if principal == Principal::anonymous() {
+        return Err(ApiError::InvalidArgument(
+            "roles cannot be granted to the anonymous principal".to_string(),
+        ));
+    }
+    ROLES.with(|r| r.borrow_mut().insert(principal, role));
+    Ok(())
+}
+
+// true jeśli principal miał jakąś rolę
+pub fn revoke(principal: &Principal) -> bool {
+    ROLES.with(|r| r.borrow_mut().remove(principal).is_some())
+}
+
+pub fn assignments() -> Vec<RoleAssignment> {
+    ROLES.with(|r| {
+        r.borrow()
+            .iter()
+            .map(|(principal, role)| RoleAssignment {
+                principal: *principal,
+                role: *role,
+            })
+            .collect()
+    })
+}
+
+pub fn restore(assignments: Vec<RoleAssignment>) {
+    ROLES.with(|r| {
+        *r.borrow_mut() = assignments
+            .into_iter()
+            .map(|assignment| (assignment.principal, assignment.role))
+            .collect()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(byte: u8) -> Principal {
+        Principal::from_slice(&[byte; 29])
+    }
+
+    #[test]
+    fn roles_cover_the_ones_below_them() {
+        assert_eq!(check(Some(Role::Controller), Role::Writer), Ok(()));
+        assert_eq!(check(Some(Role::Writer), Role::Writer), Ok(()));
+        assert_eq!(check(Some(Role::Writer), Role::Reader), Ok(()));
+        assert_eq!(
+            check(Some(Role::Reader), Role::Writer),
+            Err(ApiError::Unauthorized {
+                required: Role::Writer
+            })
+        );
+        assert_eq!(
+            check(None, Role::Reader),
+            Err(ApiError::Unauthorized {
+                required: Role::Reader
+            })
+        );
+    }
+
+    #[test]
+    fn granted_reader_cannot_write() {
+        let reader = principal(1);
+        grant(reader, Role::Reader).unwrap();
+        assert_eq!(granted_role(&reader), Some(Role::Reader));
+        assert!(check(granted_role(&reader), Role::Reader).is_ok());
+        assert!(check(granted_role(&reader), Role::Writer).is_err());
+
+        // Ponowne nadanie zastępuje rolę
+        grant(reader, Role::Writer).unwrap();
+        assert!(check(granted_role(&reader), Role::Writer).is_ok());
+    }
+
+    #[test]
+    fn controller_and_anonymous_cannot_be_granted() {
+        assert!(matches!(
+            grant(principal(2), Role::Controller),
+            Err(ApiError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            grant(Principal::anonymous(), Role::Reader),
+            Err(ApiError::InvalidArgument(_))
+        ));
+        assert_eq!(granted_role(&principal(2)), None);
+        assert_eq!(granted_role(&Principal::anonymous()), None);
+    }
+
+    #[test]
+    fn revoke_removes_the_role() {
+        let writer = principal(3);
+        grant(writer, Role::Writer).unwrap();
+        assert!(revoke(&writer));
+        assert_eq!(granted_role(&writer), None);
+        assert!(check(granted_role(&writer), Role::Reader).is_err());
+        assert!(!revoke(&writer));
+    }
+
+    #[test]
+    fn assignments_survive_restore() {
+        grant(principal(4), Role::Writer).unwrap();
+        let saved = assignments();
+        restore(Vec::new());
+        assert_eq!(granted_role(&principal(4)), None);
+        restore(saved);
+        assert_eq!(granted_role(&principal(4)), Some(Role::Writer));
+    }
+}
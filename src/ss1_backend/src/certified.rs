@@ -0,0 +1,109 @@
+// Certyfikowany root SMT. Odpowiedź na query daje jedna replika, więc sam
+// get_root wymaga zaufania do jednego węzła. Po każdej zmianie drzewa root i
+// wersja trafiają do certified data, a klient sprawdza je kluczem publicznym IC.
+//
+// Certyfikowane drzewo:
+//   fork(labeled("http_assets", { ścieżka URL: sha256(body) }),
+//        labeled("smt", { "root": 32 bajty LE, "version": u64 BE }))
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use candid::CandidType;
+use ciborium::tag::Required;
+use ic_certification::{
+    fork, fork_hash, labeled, labeled_hash, pruned, AsHashTree, Hash, HashTree, RbTree,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use zk_smt::field_to_bytes;
+
+use crate::{http, RegistryTree};
+
+const SMT_LABEL: &[u8] = b"smt";
+const HTTP_ASSETS_LABEL: &[u8] = b"http_assets";
+// Tag CBOR 55799 (self-describe) na początku witnessa
+const SELF_DESCRIBE_TAG: u64 = 55799;
+
+thread_local! {
+    static CERTIFIED: RefCell<RbTree<&'static str, Vec<u8>>> = const { RefCell::new(RbTree::new()) };
+    static HTTP_ASSETS: RefCell<RbTree<&'static str, Hash>> = const { RefCell::new(RbTree::new()) };
+}
+
+#[derive(Serialize, CandidType)]
+pub struct CertifiedRoot {
+    pub root: String,
+    pub version: u64,
+    // None przy wywołaniu jako update - IC daje certyfikat tylko w query
+    pub certificate: Option<Vec<u8>>,
+    // Hash tree w CBOR (z tagiem self-describe), jak w interface spec
+    pub witness: Vec<u8>,
+}
+
+// Wołane po każdym zapisie do drzewa, także po init i upgrade
+pub fn update(tree: &RegistryTree) {
+    CERTIFIED.with(|c| {
+        let mut certified = c.borrow_mut();
+        certified.insert("root", field_to_bytes(tree.root()));
+        certified.insert("version", tree.version().to_be_bytes().to_vec());
+    });
+    HTTP_ASSETS.with(|a| {
+        let mut assets = a.borrow_mut();
+        for (path, body) in http::certified_responses(tree) {
+            assets.insert(path, Sha256::digest(&body).into());
+        }
+    });
+    ic_cdk::api::set_certified_data(&fork_hash(&http_assets_hash(), &smt_hash()));
+}
+
+pub fn certified_root(tree: &RegistryTree) -> CertifiedRoot {
+    let witness = CERTIFIED.with(|c| {
+        fork(
+            pruned(http_assets_hash()),
+            labeled(SMT_LABEL, c.borrow().value_range(b"root", b"version")),
+        )
+    });
+    CertifiedRoot {
+        root: hex::encode(field_to_bytes(tree.root())),
+        version: tree.version(),
+        certificate: ic_cdk::api::data_certificate(),
+        witness: encode_witness(&witness),
+    }
+}
+
+// Nagłówek IC-Certificate (certyfikacja odpowiedzi HTTP w wersji 1);
+// None poza query albo dla ścieżki, której update nie certyfikuje
+pub fn http_certificate_header(path: &str) -> Option<(String, String)> {
+    let certificate = ic_cdk::api::data_certificate()?;
+    let witness = HTTP_ASSETS.with(|a| {
+        let assets = a.borrow();
+        assets.get(path.as_bytes())?;
+        Some(fork(
+            labeled(HTTP_ASSETS_LABEL, assets.witness(path.as_bytes())),
+            pruned(smt_hash()),
+        ))
+    })?;
+    Some((
+        "IC-Certificate".to_string(),
+        format!(
+            "certificate=:{}:, tree=:{}:",
+            BASE64.encode(certificate),
+            BASE64.encode(encode_witness(&witness))
+        ),
+    ))
+}
+
+fn smt_hash() -> Hash {
+    CERTIFIED.with(|c| labeled_hash(SMT_LABEL, &c.borrow().root_hash()))
+}
+
+fn http_assets_hash() -> Hash {
+    HTTP_ASSETS.with(|a| labeled_hash(HTTP_ASSETS_LABEL, &a.borrow().root_hash()))
+}
+
+fn encode_witness(tree: &HashTree) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&Required::<_, SELF_DESCRIBE_TAG>(tree), &mut bytes)
+        .expect("hash tree is always serializable");
+    bytes
+}
@@ -0,0 +1,179 @@
+// Rejestr circuitów: klucz weryfikacyjny, układ sygnałów publicznych i opis,
+// pod identyfikatorem circuitu
+
+use ark_bn254::{Bn254, Fr as Bn254Fr};
+use ark_groth16::{prepare_verifying_key, Groth16, PreparedVerifyingKey};
+use ark_snark::SNARK;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use zk_smt::snarkjs::{
+    parse_verifying_key, public_signals_from_strings, verifying_key_to_json, SnarkjsProof,
+};
+
+use crate::error::VerifyError;
+
+pub const ZK_MEMBERSHIP_CIRCUIT: &str = "zk_membership";
+pub const MINIMAL_SMT_CIRCUIT: &str = "minimal_smt";
+
+// Klucze z trusted setup dołączone do wasm
+const ZK_MEMBERSHIP_VKEY: &str = include_str!("../zk_membership_vkey.json");
+const MINIMAL_SMT_VKEY: &str = include_str!("../minimal_smt_vkey.json");
+
+thread_local! {
+    static CIRCUITS: RefCell<BTreeMap<String, Circuit>> = RefCell::new(default_circuits());
+}
+
+pub struct Circuit {
+    // Przygotowany raz przy rejestracji, nie przy każdej weryfikacji
+    pub key: PreparedVerifyingKey<Bn254>,
+    pub vkey_json: String,
+    pub hash: String,
+    pub description: String,
+    pub public_signals: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, CandidType, Clone)]
+pub struct CircuitRegistration {
+    pub circuit_id: String,
+    pub description: String,
+    pub public_signals: Vec<String>,
+    pub vkey_json: String,
+}
+
+#[derive(Serialize, CandidType)]
+pub struct CircuitInfo {
+    pub circuit_id: String,
+    pub description: String,
+    pub public_signals: Vec<String>,
+    pub hash: String,
+    pub n_public: u64,
+}
+
+impl Circuit {
+    // Parsuje klucz i normalizuje JSON, żeby hash nie zależał od formatowania
+    pub fn new(
+        vkey_json: &str,
+        description: String,
+        public_signals: Vec<String>,
+    ) -> Result<Self, VerifyError> {
+        let key = parse_verifying_key::<Bn254>(vkey_json)?;
+        let n_public = key.gamma_abc_g1.len() - 1;
+        // Opisane sygnały muszą odpowiadać wejściom publicznym klucza
+        if public_signals.len() != n_public {
+            return Err(VerifyError::WrongSignalCount {
+                expected: n_public as u64,
+                actual: public_signals.len() as u64,
+            });
+        }
+
+        let vkey_json = verifying_key_to_json(&key);
+        let hash = hex::encode(Sha256::digest(vkey_json.as_bytes()));
+        let key = prepare_verifying_key(&key);
+        Ok(Self { key, vkey_json, hash, description, public_signals })
+    }
+
+    pub fn info(&self, circuit_id: &str) -> CircuitInfo {
+        CircuitInfo {
+            circuit_id: circuit_id.to_string(),
+            description: self.description.clone(),
+            public_signals: self.public_signals.clone(),
+            hash: self.hash.clone(),
+            n_public: self.public_signals.len() as u64,
+        }
+    }
+
+    pub fn registration(&self, circuit_id: &str) -> CircuitRegistration {
+        CircuitRegistration {
+            circuit_id: circuit_id.to_string(),
+            description: self.description.clone(),
+            public_signals: self.public_signals.clone(),
+            vkey_json: self.vkey_json.clone(),
+        }
+    }
+}
+
+fn default_circuits() -> BTreeMap<String, Circuit> {
+    let defaults = [
+        (
+            ZK_MEMBERSHIP_CIRCUIT,
+            ZK_MEMBERSHIP_VKEY,
+            "Membership of a user in the SMT without revealing the secret value",
+            ["publicKey", "expectedRoot"],
+        ),
+        (
+            MINIMAL_SMT_CIRCUIT,
+            MINIMAL_SMT_VKEY,
+            "Knowledge of value and nonce such that key² + value² + nonce² = root",
+            ["root", "key"],
+        ),
+    ];
+
+    let mut circuits = BTreeMap::new();
+    for (circuit_id, vkey_json, description, signals) in defaults {
+        let signals = signals.iter().map(|s| s.to_string()).collect();
+        match Circuit::new(vkey_json, description.to_string(), signals) {
+            Ok(circuit) => {
+                circuits.insert(circuit_id.to_string(), circuit);
+            }
+            Err(e) => ic_cdk::println!("Failed to load bundled circuit {}: {}", circuit_id, e),
+        }
+    }
+    circuits
+}
+
+pub fn with_circuits<R>(f: impl FnOnce(&BTreeMap<String, Circuit>) -> R) -> R {
+    CIRCUITS.with(|c| f(&c.borrow()))
+}
+
+pub fn with_circuits_mut<R>(f: impl FnOnce(&mut BTreeMap<String, Circuit>) -> R) -> R {
+    CIRCUITS.with(|c| f(&mut c.borrow_mut()))
+}
+
+pub fn register(registration: CircuitRegistration) -> Result<String, VerifyError> {
+    let circuit = Circuit::new(
+        &registration.vkey_json,
+        registration.description,
+        registration.public_signals,
+    )?;
+    let hash = circuit.hash.clone();
+    with_circuits_mut(|c| c.insert(registration.circuit_id, circuit));
+    Ok(hash)
+}
+
+// Sprawdza proof Groth16 względem klucza zarejestrowanego dla circuitu
+pub fn verify_groth16(
+    circuit_id: &str,
+    proof: &SnarkjsProof,
+    public_signals: &[String],
+) -> Result<(), VerifyError> {
+    let proof = proof
+        .to_proof::<Bn254>()
+        .map_err(|e| VerifyError::MalformedProof(e.to_string()))?;
+    let inputs = public_signals_from_strings::<Bn254Fr>(public_signals)?;
+
+    with_circuits(|circuits| {
+        let circuit = circuits
+            .get(circuit_id)
+            .ok_or_else(|| VerifyError::UnknownCircuit(circuit_id.to_string()))?;
+        if inputs.len() != circuit.public_signals.len() {
+            return Err(VerifyError::WrongSignalCount {
+                expected: circuit.public_signals.len() as u64,
+                actual: inputs.len() as u64,
+            });
+        }
+
+        match Groth16::<Bn254>::verify_with_processed_vk(&circuit.key, &inputs, &proof) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(VerifyError::ProofRejected(
+                "Groth16 pairing check failed".to_string(),
+            )),
+            Err(e) => Err(VerifyError::ProofRejected(format!(
+                "Groth16 verification error: {}",
+                e
+            ))),
+        }
+    })
+}
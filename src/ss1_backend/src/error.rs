@@ -0,0 +1,101 @@
+// Błędy zwracane przez API jako Candid variant, zamiast `false`, trapa
+// albo tekstu "ERROR: ..." zakodowanego w hex
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::acl::Role;
+
+#[derive(Serialize, Deserialize, CandidType, Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    // Proof nie daje się odczytać (hex, JSON, punkty krzywej)
+    MalformedProof(String),
+    // Root, klucz albo sygnał publiczny w złym formacie
+    BadEncoding(String),
+    WrongPathLength { expected: u64, actual: u64 },
+    PathDirectionMismatch { height: u64 },
+    RootMismatch,
+    // Root spoza okna historii (zob. set_root_history_limit)
+    UnknownRoot,
+    KeyNotFound,
+    KeyMismatch { expected: String, actual: String },
+    // Bez wartości - to sekret z proof'a
+    ValueMismatch,
+    UnknownCircuit(String),
+    WrongSignalCount { expected: u64, actual: u64 },
+    ProofRejected(String),
+}
+
+impl From<zk_smt::Error> for VerifyError {
+    fn from(e: zk_smt::Error) -> Self {
+        match e {
+            zk_smt::Error::WrongPathLength { expected, actual } => VerifyError::WrongPathLength {
+                expected: expected as u64,
+                actual: actual as u64,
+            },
+            zk_smt::Error::PathDirectionMismatch { height } => {
+                VerifyError::PathDirectionMismatch { height: height as u64 }
+            }
+            zk_smt::Error::MalformedProof(reason) => VerifyError::MalformedProof(reason.to_string()),
+            zk_smt::Error::RootMismatch => VerifyError::RootMismatch,
+            zk_smt::Error::UnknownRoot => VerifyError::UnknownRoot,
+            zk_smt::Error::KeyNotFound => VerifyError::KeyNotFound,
+            zk_smt::Error::BadEncoding(reason) => VerifyError::BadEncoding(reason),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::MalformedProof(reason) => write!(f, "malformed proof: {}", reason),
+            VerifyError::BadEncoding(reason) => write!(f, "bad encoding: {}", reason),
+            VerifyError::WrongPathLength { expected, actual } => {
+                write!(f, "path has {} siblings, expected {}", actual, expected)
+            }
+            VerifyError::PathDirectionMismatch { height } => {
+                write!(f, "path direction at height {} does not match the key", height)
+            }
+            VerifyError::RootMismatch => write!(f, "proof does not lead to the expected root"),
+            VerifyError::UnknownRoot => write!(f, "root is not current or recent"),
+            VerifyError::KeyNotFound => write!(f, "key not found"),
+            VerifyError::KeyMismatch { expected, actual } => {
+                write!(f, "proof is for key {}, expected {}", actual, expected)
+            }
+            VerifyError::ValueMismatch => write!(f, "proof value does not match the stored value"),
+            VerifyError::UnknownCircuit(id) => write!(f, "unknown circuit {}", id),
+            VerifyError::WrongSignalCount { expected, actual } => {
+                write!(f, "expected {} public signals, got {}", expected, actual)
+            }
+            VerifyError::ProofRejected(reason) => write!(f, "proof rejected: {}", reason),
+        }
+    }
+}
+
+// Błędy wywołań zmieniających stan (zapisy, zarządzanie rolami i circuitami)
+#[derive(Serialize, Deserialize, CandidType, Clone, Debug, PartialEq, Eq)]
+pub enum ApiError {
+    Unauthorized { required: Role },
+    InvalidArgument(String),
+    // Odrzucony klucz weryfikacyjny albo nieznany circuit
+    Verification(VerifyError),
+}
+
+impl From<VerifyError> for ApiError {
+    fn from(e: VerifyError) -> Self {
+        ApiError::Verification(e)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Unauthorized { required } => {
+                write!(f, "caller does not have the {:?} role", required)
+            }
+            ApiError::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            ApiError::Verification(e) => write!(f, "{}", e),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// Interfejs HTTP (JSON) dla klientów bez Candid: curl, zwykłe serwisy.
+// /root i /stats są certyfikowane z góry (nagłówek IC-Certificate). Dowodów nie
+// da się certyfikować dla każdego klucza, a niecertyfikowaną odpowiedź gateway
+// odrzuci, więc wszystko poza tymi dwiema ścieżkami (dowody, 404, 405) query
+// odsyła do http_request_update, gdzie odpowiedź przechodzi przez konsensus.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::borrow::Cow;
+use zk_smt::{field_to_bytes, get_merkle_path, get_non_membership_proof};
+
+use crate::{certified, to_proof_entries, RegistryTree};
+
+#[derive(Deserialize, CandidType)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Serialize, CandidType)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub upgrade: Option<bool>,
+}
+
+impl HttpResponse {
+    fn json(status_code: u16, body: serde_json::Value) -> Self {
+        Self {
+            status_code,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+            upgrade: None,
+        }
+    }
+
+    fn error(status_code: u16, message: String) -> Self {
+        Self::json(status_code, json!({ "error": message }))
+    }
+
+    fn upgrade() -> Self {
+        Self {
+            status_code: 200,
+            headers: Vec::new(),
+            body: Vec::new(),
+            upgrade: Some(true),
+        }
+    }
+}
+
+// Ścieżki, których odpowiedzi zależą tylko od stanu drzewa
+const CERTIFIED_ROUTES: [&str; 2] = ["/root", "/stats"];
+
+// Ciała odpowiedzi, które certified::update certyfikuje po każdej zmianie
+pub fn certified_responses(tree: &RegistryTree) -> Vec<(&'static str, Vec<u8>)> {
+    CERTIFIED_ROUTES
+        .iter()
+        .map(|path| (*path, route(tree, "GET", path).body))
+        .collect()
+}
+
+pub fn serve_query(tree: &RegistryTree, req: &HttpRequest) -> HttpResponse {
+    let path = path_of(&req.url);
+    if answered_in_query(&req.method, path) {
+        if let Some(header) = certified::http_certificate_header(path) {
+            let mut response = route(tree, &req.method, path);
+            response.headers.push(header);
+            return response;
+        }
+    }
+    HttpResponse::upgrade()
+}
+
+// Z query odpowiadają tylko ścieżki, dla których jest certyfikat
+fn answered_in_query(method: &str, path: &str) -> bool {
+    method == "GET" && CERTIFIED_ROUTES.contains(&path)
+}
+
+pub fn serve_update(tree: &RegistryTree, req: &HttpRequest) -> HttpResponse {
+    route(tree, &req.method, path_of(&req.url))
+}
+
+// Bez query stringa; certyfikat jest wystawiany na samą ścieżkę
+fn path_of(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or_default()
+}
+
+// Klucz w ścieżce jest zakodowany procentowo (np. spacja jako %20)
+fn decode_key(key: &str) -> Result<Cow<'_, str>, HttpResponse> {
+    urlencoding::decode(key)
+        .map_err(|_| HttpResponse::error(400, "key is not valid UTF-8".to_string()))
+}
+
+fn route(tree: &RegistryTree, method: &str, path: &str) -> HttpResponse {
+    if method != "GET" {
+        return HttpResponse::error(405, format!("method {} is not allowed", method));
+    }
+
+    let root = hex::encode(field_to_bytes(tree.root()));
+    if path == "/root" {
+        return HttpResponse::json(200, json!({ "root": root, "version": tree.version() }));
+    }
+    if path == "/stats" {
+        // Bez cykli i liczników - te zmieniają się bez zapisu, a odpowiedź jest certyfikowana
+        return HttpResponse::json(
+            200,
+            json!({
+                "root": root,
+                "version": tree.version(),
+                "entries": tree.data.len(),
+                "nodes": tree.node_count(),
+            }),
+        );
+    }
+
+    if let Some(key) = path.strip_prefix("/proof/") {
+        let key = match decode_key(key) {
+            Ok(key) => key,
+            Err(response) => return response,
+        };
+        return match get_merkle_path(tree, &*key) {
+            Ok(path) => HttpResponse::json(
+                200,
+                json!({ "key": key, "root": root, "path": to_proof_entries(path) }),
+            ),
+            Err(_) => HttpResponse::error(404, format!("key {} not found", key)),
+        };
+    }
+
+    if let Some(key) = path.strip_prefix("/nonmembership/") {
+        let key = match decode_key(key) {
+            Ok(key) => key,
+            Err(response) => return response,
+        };
+        return match get_non_membership_proof(tree, &*key) {
+            Some(proof) => HttpResponse::json(
+                200,
+                json!({ "key": key, "root": root, "path": to_proof_entries(proof.path) }),
+            ),
+            None => HttpResponse::error(409, format!("key {} is registered", key)),
+        };
+    }
+
+    HttpResponse::error(404, format!("no route for {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use zk_smt::{insert_to_tree, Sha256Hasher};
+
+    use super::*;
+
+    fn tree() -> RegistryTree {
+        let mut tree = RegistryTree::with_hasher(Sha256Hasher);
+        insert_to_tree(&mut tree, "alice", &7);
+        insert_to_tree(&mut tree, "jan kowalski", &8);
+        tree
+    }
+
+    fn body(response: &HttpResponse) -> serde_json::Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[test]
+    fn routes_answer_with_their_status() {
+        let tree = tree();
+        let cases = [
+            ("GET", "/root", 200),
+            ("GET", "/stats", 200),
+            ("GET", "/proof/alice", 200),
+            ("GET", "/proof/jan%20kowalski", 200),
+            ("GET", "/proof/bob", 404),
+            ("GET", "/proof/%FF", 400),
+            ("GET", "/nonmembership/bob", 200),
+            ("GET", "/nonmembership/alice", 409),
+            ("GET", "/nonmembership/%FF", 400),
+            ("GET", "/", 404),
+            ("GET", "/favicon.ico", 404),
+            ("POST", "/root", 405),
+            ("PUT", "/proof/alice", 405),
+        ];
+        for (method, path, status) in cases {
+            let response = route(&tree, method, path);
+            assert_eq!(response.status_code, status, "{} {}", method, path);
+            assert_eq!(response.upgrade, None);
+            assert!(body(&response).is_object());
+        }
+    }
+
+    #[test]
+    fn bodies_carry_the_root_and_the_key() {
+        let tree = tree();
+        let root = hex::encode(field_to_bytes(tree.root()));
+
+        let response = body(&route(&tree, "GET", "/root"));
+        assert_eq!(response["root"], root.as_str());
+        assert_eq!(response["version"], 2);
+        assert_eq!(body(&route(&tree, "GET", "/stats"))["entries"], 2);
+
+        let response = body(&route(&tree, "GET", "/proof/jan%20kowalski"));
+        assert_eq!(response["key"], "jan kowalski");
+        assert_eq!(response["root"], root.as_str());
+        assert_eq!(response["path"].as_array().unwrap().len(), 256);
+    }
+
+    #[test]
+    fn only_certified_routes_are_answered_in_the_query() {
+        assert!(answered_in_query("GET", "/root"));
+        assert!(answered_in_query("GET", "/stats"));
+        for (method, path) in [
+            ("GET", "/proof/alice"),
+            ("GET", "/nonmembership/bob"),
+            ("GET", "/nope"),
+            ("POST", "/root"),
+        ] {
+            assert!(!answered_in_query(method, path), "{} {}", method, path);
+        }
+        assert_eq!(path_of("/root?x=1#top"), "/root");
+    }
+}
@@ -1,39 +1,174 @@
 //=== 2. Canister Code ===
 
-use ark_bls12_381::Bls12_381;
-use ark_ff::{PrimeField, BigInteger};
-use ark_groth16::{VerifyingKey, Proof};
-use ark_serialize::CanonicalDeserialize;
-use ic_cdk_macros::{query, update, init};
+use ic_cdk_macros::{query, update, init, pre_upgrade, post_upgrade};
 use serde::{Deserialize, Serialize};
 use candid::CandidType;
 use std::cell::RefCell;
-use zk_smt::{get_merkle_path, insert_to_tree, delete_from_tree, SparseMerkleTree};
+use ark_bls12_381::Fr;
+use zk_smt::{
+    apply_batch as apply_batch_to_tree, get_compressed_merkle_path, get_merkle_path,
+    get_merkle_proof_at as get_merkle_proof_at_version, get_root_at as get_root_at_version,
+    get_multi_proof as get_multi_proof_for,
+    get_non_membership_proof as get_non_membership_proof_for, delete_with_proof, insert_to_tree,
+    insert_with_proof, is_recent_root, field_to_bytes, field_to_decimal, CompressedMerkleTreePath,
+    MerkleTreePath, Sha256Hasher, SparseMerkleTree, UpdateProof, WriteBatch,
+};
+use zk_smt::snarkjs::{parse_field, SnarkjsProof};
+
+mod acl;
+mod certified;
+mod circuits;
+mod error;
+mod http;
+mod listing;
+mod stable;
+mod stats;
+
+use acl::{Role, RoleAssignment};
+use candid::Principal;
+use certified::CertifiedRoot;
+use circuits::{CircuitInfo, CircuitRegistration, ZK_MEMBERSHIP_CIRCUIT};
+use error::{ApiError, VerifyError};
+use http::{HttpRequest, HttpResponse};
+use listing::EntryPage;
+use stats::SmtStats;
+
+// Nazwa użytkownika -> ID; ID haszowane dziesiętnie, więc rooty są takie same jak przy String
+pub(crate) type RegistryTree = SparseMerkleTree<Fr, Sha256Hasher, String, u64>;
 
 thread_local! {
-    static SMT: RefCell<SparseMerkleTree> = RefCell::new(SparseMerkleTree::new());
-    static VERIFYING_KEY: RefCell<Option<VerifyingKey<Bls12_381>>> = RefCell::new(None);
+    static SMT: RefCell<RegistryTree> = RefCell::new(RegistryTree::with_hasher(Sha256Hasher));
+}
+
+// Po każdym zapisie: nowy certyfikowany root i czas zapisu do statystyk
+fn after_mutation(tree: &RegistryTree) {
+    certified::update(tree);
+    stats::record_mutation();
+}
+
+#[derive(Serialize, CandidType)]
+struct VerifyingKeyInfo {
+    circuit_id: String,
+    vkey_json: String,
+    hash: String,
+    n_public: u64,
 }
 
 // Struktura dla prawdziwego ZK proof (Groth16)
-#[derive(Serialize, Deserialize, CandidType, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 struct RealZKProof {
-    proof: Groth16Proof,
+    proof: SnarkjsProof,
+    #[serde(alias = "publicSignals")]
     public_signals: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, CandidType, Clone)]
-struct Groth16Proof {
-    pi_a: [String; 2],
-    pi_b: [[String; 2]; 2],
-    pi_c: [String; 2],
-}
-
 #[init]
 fn init() {
-    // W rzeczywistej implementacji verification key byłby ładowany z pliku
-    // Na razie używamy mockowego klucza
-    ic_cdk::println!("Canister initialized with ZK verification");
+    SMT.with(|t| certified::update(&t.borrow()));
+    let count = circuits::with_circuits(|c| c.len());
+    ic_cdk::println!("Canister initialized with ZK verification ({} circuits registered)", count);
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let registrations: Vec<CircuitRegistration> = circuits::with_circuits(|c| {
+        c.iter().map(|(id, circuit)| circuit.registration(id)).collect()
+    });
+    SMT.with(|tree| stable::save(&tree.borrow(), &registrations, &acl::assignments()))
+        .expect("failed to save canister state");
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    // Błąd odczytu przerywa upgrade, zamiast startować z pustym drzewem
+    match stable::restore() {
+        Ok(Some(state)) => {
+            SMT.with(|tree| *tree.borrow_mut() = state.tree);
+            acl::restore(state.roles);
+            for registration in state.registrations {
+                let circuit_id = registration.circuit_id.clone();
+                if let Err(e) = circuits::register(registration) {
+                    ic_cdk::println!("Dropping circuit {}: {}", circuit_id, e);
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(e) => ic_cdk::trap(&format!("Failed to restore canister state: {}", e)),
+    }
+
+    // Certified data nie przetrwa upgrade'u - także przy pustej pamięci stable
+    SMT.with(|t| certified::update(&t.borrow()));
+}
+
+// Rejestruje nowy circuit lub nadpisuje istniejący
+#[update]
+fn register_circuit(registration: CircuitRegistration) -> Result<String, ApiError> {
+    stats::count_call("register_circuit");
+    acl::require(Role::Controller)?;
+    let circuit_id = registration.circuit_id.clone();
+    let hash = circuits::register(registration)?;
+    ic_cdk::println!("Registered circuit {} (sha256 {})", circuit_id, hash);
+    Ok(hash)
+}
+
+#[query]
+fn list_circuits() -> Vec<CircuitInfo> {
+    circuits::with_circuits(|c| c.iter().map(|(id, circuit)| circuit.info(id)).collect())
+}
+
+// Podmienia klucz weryfikacyjny (np. po nowym trusted setup), zachowując
+// opis i układ sygnałów publicznych
+#[update]
+fn set_verifying_key(circuit_id: String, vkey_json: String) -> Result<String, ApiError> {
+    stats::count_call("set_verifying_key");
+    acl::require(Role::Controller)?;
+    let (description, public_signals) = circuits::with_circuits(|c| {
+        c.get(&circuit_id)
+            .map(|circuit| (circuit.description.clone(), circuit.public_signals.clone()))
+            .ok_or_else(|| VerifyError::UnknownCircuit(circuit_id.clone()))
+    })?;
+
+    let hash = circuits::register(CircuitRegistration {
+        circuit_id: circuit_id.clone(),
+        description,
+        public_signals,
+        vkey_json,
+    })?;
+    ic_cdk::println!("Installed verifying key for {} (sha256 {})", circuit_id, hash);
+    Ok(hash)
+}
+
+#[query]
+fn get_verifying_key(circuit_id: String) -> Option<VerifyingKeyInfo> {
+    circuits::with_circuits(|c| {
+        c.get(&circuit_id).map(|circuit| VerifyingKeyInfo {
+            circuit_id: circuit_id.clone(),
+            vkey_json: circuit.vkey_json.clone(),
+            hash: circuit.hash.clone(),
+            n_public: circuit.public_signals.len() as u64,
+        })
+    })
+}
+
+// Jeden punkt wejścia dla wszystkich circuitów: proof_json to proof.json z snarkjs
+#[update]
+fn verify(
+    circuit_id: String,
+    proof_json: String,
+    public_signals: Vec<String>,
+) -> Result<(), VerifyError> {
+    stats::count_call("verify");
+    let proof: SnarkjsProof = serde_json::from_str(&proof_json)
+        .map_err(|e| VerifyError::MalformedProof(format!("invalid proof JSON: {}", e)))?;
+    circuits::verify_groth16(&circuit_id, &proof, &public_signals)
+}
+
+// Proof przychodzi jako hex z JSON-em w środku
+fn decode_proof_hex(proof_hex: &str) -> Result<String, VerifyError> {
+    let proof_bytes = hex::decode(proof_hex)
+        .map_err(|e| VerifyError::MalformedProof(format!("proof is not valid hex: {}", e)))?;
+    String::from_utf8(proof_bytes)
+        .map_err(|_| VerifyError::MalformedProof("proof is not valid UTF-8".to_string()))
 }
 
 #[derive(Serialize, CandidType)]
@@ -42,37 +177,198 @@ pub struct MerkleProofEntry {
     is_left: bool,
 }
 
+// Bit h w bitmapie = sibling na wysokości h jest w `siblings`, reszta to default hashe
+#[derive(Serialize, CandidType)]
+pub struct CompressedMerkleProof {
+    bitmap: String,
+    siblings: Vec<String>,
+}
+
+impl From<CompressedMerkleTreePath> for CompressedMerkleProof {
+    fn from(proof: CompressedMerkleTreePath) -> Self {
+        Self {
+            bitmap: hex::encode(proof.bitmap),
+            siblings: proof
+                .siblings
+                .into_iter()
+                .map(|s| hex::encode(field_to_bytes(s)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, CandidType)]
+pub struct MultiProofNodeEntry {
+    height: u64,
+    index: String,
+    hash: String,
+}
+
+// Jeden dowód dla wielu nazw; brakujące węzły to default hashe
+#[derive(Serialize, CandidType)]
+pub struct MultiProofResponse {
+    root: String,
+    nodes: Vec<MultiProofNodeEntry>,
+}
+
+// Przejście old_root -> new_root, do sprawdzenia przez indeksery bez zaufania do canistra
+#[derive(Serialize, CandidType)]
+pub struct UpdateProofEntry {
+    old_value: Option<u64>,
+    new_value: Option<u64>,
+    old_root: String,
+    new_root: String,
+    path: CompressedMerkleProof,
+}
+
+impl From<UpdateProof<Fr, String, u64>> for UpdateProofEntry {
+    fn from(proof: UpdateProof<Fr, String, u64>) -> Self {
+        Self {
+            old_value: proof.old_value,
+            new_value: proof.new_value,
+            old_root: hex::encode(field_to_bytes(proof.old_root)),
+            new_root: hex::encode(field_to_bytes(proof.new_root)),
+            path: proof.path.into(),
+        }
+    }
+}
+
+// Tak samo jak w apply_batch: pusta nazwa nie jest kluczem rejestru
+fn check_name(name: &str) -> Result<(), ApiError> {
+    if name.is_empty() {
+        return Err(ApiError::InvalidArgument("Name is empty".to_string()));
+    }
+    Ok(())
+}
+
 #[update]
-fn insert(name: String, id: u64) {
-    SMT.with(|t| insert_to_tree(&mut t.borrow_mut(), &name, &id.to_string()));
+fn insert(name: String, id: u64) -> Result<UpdateProofEntry, ApiError> {
+    stats::count_call("insert");
+    acl::require(Role::Writer)?;
+    check_name(&name)?;
+    let proof = SMT.with(|t| {
+        let mut tree = t.borrow_mut();
+        let proof = insert_with_proof(&mut tree, &name, &id);
+        after_mutation(&tree);
+        proof
+    });
+    Ok(proof.into())
 }
 
 #[update]
-fn delete(name: String) {
-    SMT.with(|t| delete_from_tree(&mut t.borrow_mut(), &name));
+fn delete(name: String) -> Result<UpdateProofEntry, ApiError> {
+    stats::count_call("delete");
+    acl::require(Role::Writer)?;
+    check_name(&name)?;
+    let proof = SMT.with(|t| {
+        let mut tree = t.borrow_mut();
+        let proof = delete_with_proof(&mut tree, &name);
+        after_mutation(&tree);
+        proof
+    });
+    Ok(proof.into())
+}
+
+#[derive(Deserialize, CandidType)]
+enum BatchOp {
+    Insert { name: String, id: u64 },
+    Delete(String),
+}
+
+// Każdy nowy klucz to najwyżej 2 * 256 + 2 hashe (test w zk_smt::batch), więc
+// 500 operacji to ok. 257 tys. SHA-256 - z zapasem w limicie instrukcji update
+const MAX_BATCH_SIZE: usize = 500;
+
+// Cała paczka albo nic: najpierw walidacja, potem jedno przeliczenie drzewa
+#[update]
+fn apply_batch(ops: Vec<BatchOp>) -> Result<String, ApiError> {
+    stats::count_call("apply_batch");
+    acl::require(Role::Writer)?;
+    if ops.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::InvalidArgument(format!(
+            "Batch has {} operations, the maximum is {}",
+            ops.len(),
+            MAX_BATCH_SIZE
+        )));
+    }
+    let mut batch = WriteBatch::new();
+    for (position, op) in ops.iter().enumerate() {
+        match op {
+            BatchOp::Insert { name, .. } | BatchOp::Delete(name) if name.is_empty() => {
+                return Err(ApiError::InvalidArgument(format!(
+                    "Operation {} has an empty name",
+                    position
+                )));
+            }
+            BatchOp::Insert { name, id } => batch.insert(name, id),
+            BatchOp::Delete(name) => batch.delete(name),
+        };
+    }
+
+    SMT.with(|t| {
+        let mut tree = t.borrow_mut();
+        apply_batch_to_tree(&mut tree, &batch);
+        after_mutation(&tree);
+        Ok(hex::encode(field_to_bytes(tree.root())))
+    })
+}
+
+// Nadawanie ról writer/reader; controllerów ustawia się w ustawieniach canistra
+#[update]
+fn grant_role(principal: Principal, role: Role) -> Result<(), ApiError> {
+    stats::count_call("grant_role");
+    acl::require(Role::Controller)?;
+    acl::grant(principal, role)?;
+    ic_cdk::println!("Granted {:?} to {}", role, principal);
+    Ok(())
+}
+
+#[update]
+fn revoke_role(principal: Principal) -> Result<(), ApiError> {
+    stats::count_call("revoke_role");
+    acl::require(Role::Controller)?;
+    if !acl::revoke(&principal) {
+        return Err(ApiError::InvalidArgument(format!("{} has no role to revoke", principal)));
+    }
+    ic_cdk::println!("Revoked role of {}", principal);
+    Ok(())
+}
+
+#[query]
+fn list_roles() -> Result<Vec<RoleAssignment>, ApiError> {
+    acl::require(Role::Controller)?;
+    Ok(acl::assignments())
+}
+
+// None dla wywołujących bez roli (np. anonimowych)
+#[query]
+fn get_my_role() -> Option<Role> {
+    acl::role_of(&ic_cdk::caller())
 }
 
 // === ZK Proof Generation ===
 
 #[update]
-fn generate_zk_proof(key: u64, value: u64, nonce: u64) -> String {
+fn generate_zk_proof(key: u64, value: u64, nonce: u64) -> Result<String, ApiError> {
+    stats::count_call("generate_zk_proof");
     // Sprawdź czy klucz istnieje w SMT
     let key_str = key.to_string();
-    let value_str = value.to_string();
     
     let smt_contains_key = SMT.with(|t| t.borrow().data.contains_key(&key_str));
     
     if !smt_contains_key {
-        // Jeśli klucz nie istnieje, dodaj go do SMT
+        // Jeśli klucz nie istnieje, dodaj go do SMT - to zapis, więc tylko dla writerów
+        acl::require(Role::Writer)?;
         SMT.with(|t| {
             let mut tree = t.borrow_mut();
-            insert_to_tree(&mut tree, &key_str, &value_str);
+            insert_to_tree(&mut tree, &key_str, &value);
+            after_mutation(&tree);
         });
     }
     
     // Pobierz aktualny root SMT
     let smt_root = SMT.with(|t| t.borrow().root());
-    let smt_root_value = smt_root.into_bigint().to_string();
+    let smt_root_value = field_to_decimal(smt_root);
     
     // Wygeneruj proof członkostwa dla tego klucza w SMT
     let merkle_path = SMT.with(|t| get_merkle_path(&t.borrow(), &key_str));
@@ -87,11 +383,11 @@ fn generate_zk_proof(key: u64, value: u64, nonce: u64) -> String {
         let tree = t.borrow();
         let mut sibling_idx = 0;
         
-        for (other_key, other_value) in &tree.data {
+        for (other_key, &other_value) in &tree.data {
             if other_key != &key_str && sibling_idx < 3 {
-                let other_key_num: u64 = other_key.parse().unwrap_or(0);
-                let other_value_num: u64 = other_value.parse().unwrap_or(0);
-                siblings[sibling_idx] = other_key_num * other_key_num + other_value_num * other_value_num;
+                // Pomijamy nazwy użytkowników - tylko klucze liczbowe
+                let Ok(other_key_num) = other_key.parse::<u64>() else { continue };
+                siblings[sibling_idx] = other_key_num * other_key_num + other_value * other_value;
                 sibling_idx += 1;
             }
         }
@@ -111,25 +407,153 @@ fn generate_zk_proof(key: u64, value: u64, nonce: u64) -> String {
     );
     
     // Konwertuj do hex
-    hex::encode(proof_data.as_bytes())
+    Ok(hex::encode(proof_data.as_bytes()))
 }
 
-#[query]
-fn get_merkle_proof(name: String) -> Vec<MerkleProofEntry> {
-    let tree = SMT.with(|t| t.borrow().clone());
-    let path = get_merkle_path(&tree, &name);
+fn to_proof_entries(path: MerkleTreePath) -> Vec<MerkleProofEntry> {
     path.path
         .into_iter()
         .map(|el| MerkleProofEntry {
-            hash: hex::encode(el.value),
+            hash: hex::encode(field_to_bytes(el.value)),
             is_left: el.is_left,
         })
         .collect()
 }
 
+#[query]
+fn get_merkle_proof(name: String) -> Result<Vec<MerkleProofEntry>, VerifyError> {
+    SMT.with(|t| get_merkle_path(&t.borrow(), &name))
+        .map(to_proof_entries)
+        .map_err(|_| VerifyError::KeyNotFound)
+}
+
+// Dowód, że nazwa nie jest jeszcze zarejestrowana (None jeśli istnieje)
+#[query]
+fn get_non_membership_proof(name: String) -> Option<Vec<MerkleProofEntry>> {
+    SMT.with(|t| get_non_membership_proof_for(&t.borrow(), &name))
+        .map(|proof| to_proof_entries(proof.path))
+}
+
+// Ścieżka razem z certyfikowanym rootem: klient w jednym query sprawdza
+// certyfikat, a potem ścieżkę względem certyfikowanego roota
+#[derive(Serialize, CandidType)]
+pub struct CertifiedMerkleProof {
+    path: Vec<MerkleProofEntry>,
+    certified_root: CertifiedRoot,
+}
+
+// None dla niezarejestrowanej nazwy (zob. get_certified_non_membership_proof)
+#[query]
+fn get_certified_merkle_proof(name: String) -> Option<CertifiedMerkleProof> {
+    SMT.with(|t| {
+        let tree = t.borrow();
+        get_merkle_path(&tree, &name)
+            .ok()
+            .map(|path| CertifiedMerkleProof {
+                path: to_proof_entries(path),
+                certified_root: certified::certified_root(&tree),
+            })
+    })
+}
+
+#[query]
+fn get_certified_non_membership_proof(name: String) -> Option<CertifiedMerkleProof> {
+    SMT.with(|t| {
+        let tree = t.borrow();
+        get_non_membership_proof_for(&tree, &name).map(|proof| CertifiedMerkleProof {
+            path: to_proof_entries(proof.path),
+            certified_root: certified::certified_root(&tree),
+        })
+    })
+}
+
+#[query]
+fn get_compressed_merkle_proof(name: String) -> Option<CompressedMerkleProof> {
+    SMT.with(|t| get_compressed_merkle_path(&t.borrow(), &name))
+        .ok()
+        .map(CompressedMerkleProof::from)
+}
+
+#[query]
+fn get_multi_proof(names: Vec<String>) -> MultiProofResponse {
+    let keys: Vec<&str> = names.iter().map(String::as_str).collect();
+    SMT.with(|t| {
+        let tree = t.borrow();
+        let proof = get_multi_proof_for(&tree, &keys);
+        MultiProofResponse {
+            root: hex::encode(field_to_bytes(tree.root())),
+            nodes: proof
+                .nodes
+                .into_iter()
+                .map(|node| MultiProofNodeEntry {
+                    height: node.height as u64,
+                    index: hex::encode(node.index),
+                    hash: hex::encode(field_to_bytes(node.value)),
+                })
+                .collect(),
+        }
+    })
+}
+
 #[query]
 fn get_root() -> String {
-    SMT.with(|t| hex::encode(t.borrow().root().into_bigint().to_bytes_le()))
+    SMT.with(|t| hex::encode(field_to_bytes(t.borrow().root())))
+}
+
+// JSON po HTTP dla klientów bez Candid (zob. http.rs)
+#[query]
+fn http_request(req: HttpRequest) -> HttpResponse {
+    SMT.with(|t| http::serve_query(&t.borrow(), &req))
+}
+
+#[update]
+fn http_request_update(req: HttpRequest) -> HttpResponse {
+    stats::count_call("http_request_update");
+    SMT.with(|t| http::serve_update(&t.borrow(), &req))
+}
+
+// Root i wersja z certyfikatem IC; klient sprawdza certyfikat, potem witness
+// (ścieżki smt/root i smt/version) względem certified_data z certyfikatu
+#[query]
+fn get_certified_root() -> CertifiedRoot {
+    SMT.with(|t| certified::certified_root(&t.borrow()))
+}
+
+// Numer wersji rośnie z każdym zapisem (insert, delete, apply_batch)
+#[query]
+fn get_version() -> u64 {
+    SMT.with(|t| t.borrow().version())
+}
+
+#[query]
+fn get_root_at(version: u64) -> Option<String> {
+    SMT.with(|t| get_root_at_version(&t.borrow(), version)).map(|root| hex::encode(field_to_bytes(root)))
+}
+
+// None gdy wersja wypadła z historii albo nazwy wtedy nie było
+#[query]
+fn get_merkle_proof_at(name: String, version: u64) -> Option<Vec<MerkleProofEntry>> {
+    SMT.with(|t| get_merkle_proof_at_version(&t.borrow(), &name, version)).map(to_proof_entries)
+}
+
+// Każda wersja w historii trzyma węzły nadpisane przez jeden zapis (do apply_batch
+// włącznie), więc limit jest ograniczony, żeby historia nie rosła bez końca
+const MAX_ROOT_HISTORY_LIMIT: u64 = 1024;
+
+// Ile ostatnich rootów przyjmują weryfikatory
+#[update]
+fn set_root_history_limit(limit: u64) -> Result<(), ApiError> {
+    stats::count_call("set_root_history_limit");
+    acl::require(Role::Controller)?;
+    if limit > MAX_ROOT_HISTORY_LIMIT {
+        return Err(ApiError::InvalidArgument(format!(
+            "History limit {} exceeds the maximum of {}",
+            limit, MAX_ROOT_HISTORY_LIMIT
+        )));
+    }
+    // Po sprawdzeniu limitu mieści się w usize także na wasm32
+    SMT.with(|t| t.borrow_mut().set_history_limit(limit as usize));
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize, CandidType)]
@@ -172,18 +596,9 @@ struct ZKVerifyRequest {
 }
 
 #[update]
-fn verify_zk_membership(req: ZKVerifyRequest) -> bool {
-    // Dekoduj ZK proof z hex
-    let proof_bytes = match hex::decode(&req.zk_proof) {
-        Ok(bytes) => bytes,
-        Err(_) => return false,
-    };
-    
-    // Deserializuj proof JSON
-    let proof_str = match String::from_utf8(proof_bytes) {
-        Ok(s) => s,
-        Err(_) => return false,
-    };
+fn verify_zk_membership(req: ZKVerifyRequest) -> Result<(), VerifyError> {
+    stats::count_call("verify_zk_membership");
+    let proof_str = decode_proof_hex(&req.zk_proof)?;
     
     // Spróbuj najpierw nowego formatu (UserZKProofData)
     if let Ok(proof_data) = serde_json::from_str::<UserZKProofData>(&proof_str) {
@@ -191,53 +606,39 @@ fn verify_zk_membership(req: ZKVerifyRequest) -> bool {
     }
     
     // Fallback na stary format (ZKProofData)
-    let proof_data: ZKProofData = match serde_json::from_str(&proof_str) {
-        Ok(data) => data,
-        Err(_) => return false,
-    };
+    let proof_data: ZKProofData = serde_json::from_str(&proof_str)
+        .map_err(|e| VerifyError::MalformedProof(format!("invalid proof JSON: {}", e)))?;
     
-    return verify_legacy_zk_proof(&req, &proof_data);
+    verify_legacy_zk_proof(&req, &proof_data)
 }
 
-fn verify_user_zk_proof(req: &ZKVerifyRequest, proof_data: &UserZKProofData) -> bool {
+fn verify_user_zk_proof(
+    req: &ZKVerifyRequest,
+    proof_data: &UserZKProofData,
+) -> Result<(), VerifyError> {
     ic_cdk::println!("Verifying user ZK proof for username: {}", proof_data.username);
     
     // Sprawdź czy klucz z proof'a pasuje do request'a (username)
     if proof_data.username != req.key {
-        ic_cdk::println!("Username mismatch: proof has '{}', request has '{}'", 
-                        proof_data.username, req.key);
-        return false;
+        return Err(VerifyError::KeyMismatch {
+            expected: req.key.clone(),
+            actual: proof_data.username.clone(),
+        });
     }
     
     // Sprawdź czy użytkownik rzeczywiście istnieje w SMT
     let stored_value = SMT.with(|t| t.borrow().data.get(&proof_data.username).cloned());
     
-    let stored_id = match stored_value {
-        Some(id_str) => {
-            match id_str.parse::<u64>() {
-                Ok(id) => id,
-                Err(_) => {
-                    ic_cdk::println!("Invalid ID format for user {}: {}", proof_data.username, id_str);
-                    return false;
-                }
-            }
-        },
-        None => {
-            ic_cdk::println!("User {} does not exist in SMT", proof_data.username);
-            return false;
-        }
-    };
+    let stored_id = stored_value.ok_or(VerifyError::KeyNotFound)?;
     
     // Sprawdź czy secret_value w proof pasuje do wartości w SMT
     if proof_data.secret_value != stored_id {
-        ic_cdk::println!("Secret value mismatch: proof has {}, SMT has {}", 
-                        proof_data.secret_value, stored_id);
-        return false;
+        return Err(VerifyError::ValueMismatch);
     }
     
     // Pobierz aktualny root SMT
     let current_smt_root = SMT.with(|t| t.borrow().root());
-    let current_smt_root_str = current_smt_root.into_bigint().to_string();
+    let current_smt_root_str = field_to_decimal(current_smt_root);
     
     ic_cdk::println!("SMT root comparison: proof has '{}', current is '{}'", 
                     proof_data.smt_root, current_smt_root_str);
@@ -259,28 +660,34 @@ fn verify_user_zk_proof(req: &ZKVerifyRequest, proof_data: &UserZKProofData) ->
     let intermediate_hash2 = intermediate_hash1 + proof_data.siblings[1];
     let _computed_root = intermediate_hash2 + proof_data.siblings[2];
     
-    ic_cdk::println!("ZK proof verification successful for user '{}' - returning TRUE", proof_data.username);
-    true
+    ic_cdk::println!("ZK proof verification successful for user '{}'", proof_data.username);
+    Ok(())
 }
 
-fn verify_legacy_zk_proof(req: &ZKVerifyRequest, proof_data: &ZKProofData) -> bool {
-    // Pobierz aktualny root SMT
-    let current_smt_root = SMT.with(|t| t.borrow().root());
-    let current_smt_root_str = current_smt_root.into_bigint().to_string();
-    
-    // Sprawdź czy proof odnosi się do aktualnego root SMT
-    if proof_data.smt_root != current_smt_root_str {
-        ic_cdk::println!("SMT root mismatch: proof has {}, current is {}", 
-                        proof_data.smt_root, current_smt_root_str);
-        return false;
+fn verify_legacy_zk_proof(
+    req: &ZKVerifyRequest,
+    proof_data: &ZKProofData,
+) -> Result<(), VerifyError> {
+    // Proof mógł powstać przed ostatnimi insertami - akceptujemy root z okna historii
+    let root = parse_field::<Fr>(&proof_data.smt_root)?;
+    let root_is_recent = SMT.with(|t| {
+        let tree = t.borrow();
+        is_recent_root(&tree, root, tree.history_limit())
+    });
+    if !root_is_recent {
+        return Err(VerifyError::UnknownRoot);
     }
     
     // Sprawdź czy klucz z proof'a pasuje do request'a
-    let requested_key: u64 = req.key.parse().unwrap_or(0);
+    let requested_key: u64 = req
+        .key
+        .parse()
+        .map_err(|_| VerifyError::BadEncoding(format!("key {} is not a number", req.key)))?;
     if proof_data.key != requested_key {
-        ic_cdk::println!("Key mismatch: proof has {}, request has {}", 
-                        proof_data.key, requested_key);
-        return false;
+        return Err(VerifyError::KeyMismatch {
+            expected: requested_key.to_string(),
+            actual: proof_data.key.to_string(),
+        });
     }
     
     // Sprawdź czy klucz rzeczywiście istnieje w SMT
@@ -288,8 +695,7 @@ fn verify_legacy_zk_proof(req: &ZKVerifyRequest, proof_data: &ZKProofData) -> bo
     let key_exists = SMT.with(|t| t.borrow().data.contains_key(&key_str));
     
     if !key_exists {
-        ic_cdk::println!("Key {} does not exist in SMT", proof_data.key);
-        return false;
+        return Err(VerifyError::KeyNotFound);
     }
     
     // Weryfikuj proof członkostwa używając circuit logic
@@ -307,16 +713,19 @@ fn verify_legacy_zk_proof(req: &ZKVerifyRequest, proof_data: &ZKProofData) -> bo
     
     ic_cdk::println!("ZK membership proof verified for key: {} in SMT with root: {}", 
                     proof_data.key, proof_data.smt_root);
-    true
+    Ok(())
 }
 
 // Zachowujemy starą funkcję dla kompatybilności
 #[update] 
-fn verify_query_result(req_old: VerifyRequestOld) -> bool {
-    let root_bytes = hex::decode(req_old.root).unwrap_or_default();
-    let proof_bytes = hex::decode(req_old.zk_proof).unwrap_or_default();
+fn verify_query_result(req_old: VerifyRequestOld) -> Result<(), VerifyError> {
+    stats::count_call("verify_query_result");
+    let root_bytes = hex::decode(&req_old.root)
+        .map_err(|e| VerifyError::BadEncoding(format!("root is not valid hex: {}", e)))?;
+    let proof_bytes = hex::decode(&req_old.zk_proof)
+        .map_err(|e| VerifyError::MalformedProof(format!("proof is not valid hex: {}", e)))?;
     let public_inputs = (req_old.name.as_str(), req_old.id, root_bytes.as_slice());
-    zk_smt::verify_proof(proof_bytes, public_inputs)
+    Ok(zk_smt::verify_proof(proof_bytes, public_inputs)?)
 }
 
 #[derive(Deserialize, CandidType)]
@@ -334,38 +743,22 @@ fn greet(name: String) -> String {
 }
 
 #[update]
-fn verify_real_zk_membership(public_key: String, expected_root: String, zk_proof_hex: String) -> bool {
-    // Dekoduj ZK proof z hex
-    let proof_bytes = match hex::decode(&zk_proof_hex) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            ic_cdk::println!("Failed to decode hex proof");
-            return false;
-        }
-    };
-    
-    // Deserializuj proof JSON
-    let proof_str = match String::from_utf8(proof_bytes) {
-        Ok(s) => s,
-        Err(_) => {
-            ic_cdk::println!("Failed to convert proof bytes to string");
-            return false;
-        }
-    };
-    
-    let real_proof: RealZKProof = match serde_json::from_str(&proof_str) {
-        Ok(data) => data,
-        Err(e) => {
-            ic_cdk::println!("Failed to parse ZK proof JSON: {}", e);
-            return false;
-        }
-    };
+fn verify_real_zk_membership(
+    public_key: String,
+    expected_root: String,
+    zk_proof_hex: String,
+) -> Result<(), VerifyError> {
+    stats::count_call("verify_real_zk_membership");
+    let proof_str = decode_proof_hex(&zk_proof_hex)?;
+    let real_proof: RealZKProof = serde_json::from_str(&proof_str)
+        .map_err(|e| VerifyError::MalformedProof(format!("invalid proof JSON: {}", e)))?;
     
     // Sprawdź publiczne sygnały
     if real_proof.public_signals.len() != 2 {
-        ic_cdk::println!("Invalid number of public signals: expected 2, got {}", 
-                        real_proof.public_signals.len());
-        return false;
+        return Err(VerifyError::WrongSignalCount {
+            expected: 2,
+            actual: real_proof.public_signals.len() as u64,
+        });
     }
     
     let proof_public_key = &real_proof.public_signals[0];
@@ -373,42 +766,23 @@ fn verify_real_zk_membership(public_key: String, expected_root: String, zk_proof
     
     // Sprawdź czy publiczne sygnały pasują do request'a
     if proof_public_key != &public_key {
-        ic_cdk::println!("Public key mismatch: request has {}, proof has {}", 
-                        public_key, proof_public_key);
-        return false;
+        return Err(VerifyError::KeyMismatch {
+            expected: public_key,
+            actual: proof_public_key.clone(),
+        });
     }
     
     if proof_expected_root != &expected_root {
-        ic_cdk::println!("Expected root mismatch: request has {}, proof has {}", 
-                        expected_root, proof_expected_root);
-        return false;
-    }
-    
-    // W prawdziwej implementacji tutaj używalibyśmy verification key
-    // i biblioteki ark-groth16 do weryfikacji proof'a
-    // Na razie używamy uproszczonej weryfikacji struktury
-    
-    // Sprawdź strukturę Groth16 proof
-    if real_proof.proof.pi_a.len() != 2 ||
-       real_proof.proof.pi_b.len() != 2 ||
-       real_proof.proof.pi_c.len() != 2 {
-        ic_cdk::println!("Invalid Groth16 proof structure");
-        return false;
-    }
-    
-    // Sprawdź czy wszystkie elementy proof'a są liczbami
-    for element in &real_proof.proof.pi_a {
-        if element.parse::<u64>().is_err() {
-            ic_cdk::println!("Invalid pi_a element: {}", element);
-            return false;
-        }
+        return Err(VerifyError::RootMismatch);
     }
     
+    circuits::verify_groth16(ZK_MEMBERSHIP_CIRCUIT, &real_proof.proof, &real_proof.public_signals)?;
+
     ic_cdk::println!("Real ZK proof verified successfully for key: {} with root: {}", 
                     public_key, expected_root);
     ic_cdk::println!("SECRET VALUE WAS NOT REVEALED - this is true zero-knowledge!");
     
-    true
+    Ok(())
 }
 
 // Funkcja pomocnicza do generowania danych dla ZK proof
@@ -422,7 +796,7 @@ fn get_smt_data_for_zk_proof(public_key: String) -> Option<String> {
     
     // Pobierz aktualny root SMT
     let smt_root = SMT.with(|t| t.borrow().root());
-    let smt_root_value = smt_root.into_bigint().to_string();
+    let smt_root_value = field_to_decimal(smt_root);
     
     // Wygeneruj przykładowe sibling'i dla uproszczenia
     let mut siblings = vec![100u64, 200u64, 300u64];
@@ -431,11 +805,10 @@ fn get_smt_data_for_zk_proof(public_key: String) -> Option<String> {
         let tree = t.borrow();
         let mut sibling_idx = 0;
         
-        for (other_key, other_value) in &tree.data {
+        for (other_key, &other_value) in &tree.data {
             if other_key != &public_key && sibling_idx < 3 {
-                let other_key_num: u64 = other_key.parse().unwrap_or(0);
-                let other_value_num: u64 = other_value.parse().unwrap_or(0);
-                siblings[sibling_idx] = other_key_num + other_value_num;
+                let Ok(other_key_num) = other_key.parse::<u64>() else { continue };
+                siblings[sibling_idx] = other_key_num + other_value;
                 sibling_idx += 1;
             }
         }
@@ -449,53 +822,36 @@ fn get_smt_data_for_zk_proof(public_key: String) -> Option<String> {
     Some(smt_data)
 }
 
-// Funkcja do wyświetlania wszystkich wpisów w SMT
+// Strona wpisów od kursora (ostatniego klucza z poprzedniej strony);
+// wartości tylko dla readerów, writerów i controllerów
 #[query]
-fn get_all_smt_entries() -> Vec<(String, String)> {
-    SMT.with(|t| {
-        let tree = t.borrow();
-        tree.data.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
-    })
+fn list_entries(cursor: Option<String>, limit: u64) -> EntryPage {
+    let with_values = acl::require(Role::Reader).is_ok();
+    SMT.with(|t| listing::page(&t.borrow(), "", cursor.as_deref(), limit, with_values))
 }
 
-// Funkcja do wyświetlania statystyk SMT
-#[query] 
-fn get_smt_stats() -> String {
-    SMT.with(|t| {
-        let tree = t.borrow();
-        let root = tree.root.into_bigint().to_string();
-        let count = tree.data.len();
-        
-        format!(
-            "SMT Statistics:\n- Root: {}\n- Total entries: {}\n- Entries: {:?}",
-            root, count, tree.data
-        )
-    })
+#[query]
+fn list_keys_with_prefix(prefix: String, cursor: Option<String>, limit: u64) -> EntryPage {
+    let with_values = acl::require(Role::Reader).is_ok();
+    SMT.with(|t| listing::page(&t.borrow(), &prefix, cursor.as_deref(), limit, with_values))
+}
+
+// Statystyki dla monitoringu (zob. stats.rs)
+#[query]
+fn get_smt_stats() -> SmtStats {
+    SMT.with(|t| stats::collect(&t.borrow()))
 }
 
 // Nowa, poprawna funkcja do generowania ZK proof dla użytkownika
 #[update]
-fn generate_zk_proof_for_user(username: String, nonce: u64) -> String {
+fn generate_zk_proof_for_user(username: String, nonce: u64) -> Result<String, VerifyError> {
+    stats::count_call("generate_zk_proof_for_user");
     // Sprawdź czy użytkownik istnieje w SMT
     let user_value = SMT.with(|t| {
         t.borrow().data.get(&username).cloned()
     });
     
-    let user_id = match user_value {
-        Some(id_str) => {
-            match id_str.parse::<u64>() {
-                Ok(id) => id,
-                Err(_) => {
-                    let error_msg = format!("ERROR: Invalid ID format for user {}: {}", username, id_str);
-                    return hex::encode(error_msg.as_bytes());
-                }
-            }
-        },
-        None => {
-            let error_msg = format!("ERROR: User {} not found in SMT", username);
-            return hex::encode(error_msg.as_bytes());
-        }
-    };
+    let user_id = user_value.ok_or(VerifyError::KeyNotFound)?;
     
     // Oblicz prosty hash z nazwy użytkownika jako public key
     let public_key = username.chars()
@@ -507,7 +863,7 @@ fn generate_zk_proof_for_user(username: String, nonce: u64) -> String {
     
     // Pobierz aktualny root SMT
     let smt_root = SMT.with(|t| t.borrow().root());
-    let smt_root_value = smt_root.into_bigint().to_string();
+    let smt_root_value = field_to_decimal(smt_root);
     
     // Oblicz siblings na podstawie innych użytkowników w SMT
     let mut siblings = vec![100u64, 200u64, 300u64]; // domyślne wartości
@@ -516,9 +872,8 @@ fn generate_zk_proof_for_user(username: String, nonce: u64) -> String {
         let tree = t.borrow();
         let mut sibling_idx = 0;
         
-        for (other_username, other_id_str) in &tree.data {
+        for (other_username, &other_id) in &tree.data {
             if other_username != &username && sibling_idx < 3 {
-                let other_id: u64 = other_id_str.parse().unwrap_or(0);
                 let other_hash = other_username.chars()
                     .map(|c| c as u64)
                     .fold(0u64, |acc, x| acc.wrapping_mul(31).wrapping_add(x));
@@ -540,7 +895,7 @@ fn generate_zk_proof_for_user(username: String, nonce: u64) -> String {
     );
     
     // Konwertuj do hex
-    hex::encode(proof_data.as_bytes())
+    Ok(hex::encode(proof_data.as_bytes()))
 }
 
 
@@ -0,0 +1,151 @@
+// Stronicowane listowanie wpisów SMT w kolejności kluczy (zakresy na BTreeMap).
+// Kursor to ostatni zwrócony klucz; następna strona zaczyna się zaraz za nim.
+
+use candid::CandidType;
+use serde::Serialize;
+use std::ops::Bound;
+
+use crate::RegistryTree;
+
+pub const MAX_PAGE_SIZE: u64 = 500;
+
+#[derive(Serialize, CandidType)]
+pub struct EntryView {
+    pub key: String,
+    // None dla wywołujących bez roli - ID jest sekretem
+    pub value: Option<u64>,
+}
+
+#[derive(Serialize, CandidType)]
+pub struct EntryPage {
+    pub entries: Vec<EntryView>,
+    // None na ostatniej stronie
+    pub next_cursor: Option<String>,
+}
+
+// Limit przycinany do 1..=MAX_PAGE_SIZE; pusty prefiks to wszystkie wpisy
+pub fn page(
+    tree: &RegistryTree,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: u64,
+    with_values: bool,
+) -> EntryPage {
+    let start = match cursor {
+        Some(cursor) if cursor >= prefix => Bound::Excluded(cursor),
+        _ => Bound::Included(prefix),
+    };
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+
+    let mut matching = tree
+        .data
+        .range::<str, _>((start, Bound::Unbounded))
+        .take_while(|(key, _)| key.starts_with(prefix));
+    let entries: Vec<EntryView> = matching
+        .by_ref()
+        .take(limit)
+        .map(|(key, value)| EntryView {
+            key: key.clone(),
+            value: with_values.then_some(*value),
+        })
+        .collect();
+    let next_cursor = if matching.next().is_some() {
+        entries.last().map(|entry| entry.key.clone())
+    } else {
+        None
+    };
+
+    EntryPage {
+        entries,
+        next_cursor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zk_smt::{insert_to_tree, Sha256Hasher};
+
+    use super::*;
+
+    fn tree(keys: &[&str]) -> RegistryTree {
+        let mut tree = RegistryTree::with_hasher(Sha256Hasher);
+        for (id, key) in keys.iter().enumerate() {
+            insert_to_tree(&mut tree, *key, &(id as u64));
+        }
+        tree
+    }
+
+    fn keys(page: &EntryPage) -> Vec<&str> {
+        page.entries
+            .iter()
+            .map(|entry| entry.key.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn cursor_resumes_after_the_last_key() {
+        let tree = tree(&["a", "b", "c", "d", "e"]);
+        let first = page(&tree, "", None, 2, false);
+        assert_eq!(keys(&first), ["a", "b"]);
+        assert_eq!(first.next_cursor.as_deref(), Some("b"));
+
+        let second = page(&tree, "", first.next_cursor.as_deref(), 2, false);
+        assert_eq!(keys(&second), ["c", "d"]);
+        let last = page(&tree, "", second.next_cursor.as_deref(), 2, false);
+        assert_eq!(keys(&last), ["e"]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn removed_cursor_continues_with_the_next_key() {
+        let tree = tree(&["a", "c", "e"]);
+        assert_eq!(keys(&page(&tree, "", Some("b"), 10, false)), ["c", "e"]);
+        assert_eq!(
+            keys(&page(&tree, "", Some("z"), 10, false)),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn prefix_limits_the_listing() {
+        let tree = tree(&["alice", "alicja", "bob", "al", "zed"]);
+        assert_eq!(
+            keys(&page(&tree, "ali", None, 10, false)),
+            ["alice", "alicja"]
+        );
+        assert_eq!(
+            keys(&page(&tree, "ali", Some("alice"), 10, false)),
+            ["alicja"]
+        );
+        // A cursor before the prefix starts at the prefix
+        assert_eq!(keys(&page(&tree, "b", Some("a"), 10, false)), ["bob"]);
+        assert_eq!(keys(&page(&tree, "", None, 10, false)).len(), 5);
+        assert_eq!(keys(&page(&tree, "x", None, 10, false)), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let names: Vec<String> = (0..MAX_PAGE_SIZE + 10)
+            .map(|i| format!("user{:04}", i))
+            .collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let tree = tree(&names);
+
+        let zero = page(&tree, "", None, 0, false);
+        assert_eq!(keys(&zero), ["user0000"]);
+        assert_eq!(zero.next_cursor.as_deref(), Some("user0000"));
+
+        let huge = page(&tree, "", None, u64::MAX, false);
+        assert_eq!(huge.entries.len() as u64, MAX_PAGE_SIZE);
+        assert!(huge.next_cursor.is_some());
+    }
+
+    #[test]
+    fn values_only_with_a_role() {
+        let tree = tree(&["a", "b"]);
+        let hidden = page(&tree, "", None, 10, false);
+        assert!(hidden.entries.iter().all(|entry| entry.value.is_none()));
+        let shown = page(&tree, "", None, 10, true);
+        assert_eq!(shown.entries[1].value, Some(1));
+    }
+}
@@ -0,0 +1,126 @@
+// Stan canistra w stable memory na czas upgrade'u
+//
+// Układ (liczby little-endian):
+//
+//   [u8; 4]   STATE_MAGIC
+//   u32       wersja układu (STATE_VERSION)
+//   u64       długość drzewa, potem drzewo w formacie zk_smt::serialization
+//   u64       długość rejestru, potem Vec<CircuitRegistration> w Candid
+//   u64       długość ról, potem Vec<RoleAssignment> w Candid
+//
+// Drzewo przechowuje ID jako u64, kodowane dziesiętnie.
+
+use ark_serialize::CanonicalSerialize;
+use ic_cdk::api::stable::{stable_read, stable_size, StableWriter};
+use std::io::Write;
+use zk_smt::serialization::from_trusted_bytes;
+
+use crate::acl::RoleAssignment;
+use crate::circuits::CircuitRegistration;
+use crate::RegistryTree;
+
+const STATE_MAGIC: &[u8; 4] = b"SS1S";
+pub const STATE_VERSION: u32 = 1;
+pub const WASM_PAGE_SIZE: u64 = 65536;
+
+pub struct StableState {
+    pub tree: RegistryTree,
+    pub registrations: Vec<CircuitRegistration>,
+    pub roles: Vec<RoleAssignment>,
+}
+
+pub fn save(
+    tree: &RegistryTree,
+    registrations: &[CircuitRegistration],
+    roles: &[RoleAssignment],
+) -> Result<(), String> {
+    let registry_bytes = candid::encode_one(registrations)
+        .map_err(|e| format!("failed to encode circuit registry: {}", e))?;
+    let roles_bytes =
+        candid::encode_one(roles).map_err(|e| format!("failed to encode roles: {}", e))?;
+
+    let mut writer = StableWriter::default();
+    write(&mut writer, STATE_MAGIC)?;
+    write(&mut writer, &STATE_VERSION.to_le_bytes())?;
+    // Drzewo idzie prosto do stable memory, bez kopii na heapie
+    write(&mut writer, &(tree.compressed_size() as u64).to_le_bytes())?;
+    tree.serialize_compressed(&mut writer)
+        .map_err(|e| format!("failed to write tree to stable memory: {}", e))?;
+    write(&mut writer, &(registry_bytes.len() as u64).to_le_bytes())?;
+    write(&mut writer, &registry_bytes)?;
+    write(&mut writer, &(roles_bytes.len() as u64).to_le_bytes())?;
+    write(&mut writer, &roles_bytes)
+}
+
+fn write(writer: &mut StableWriter, bytes: &[u8]) -> Result<(), String> {
+    writer
+        .write_all(bytes)
+        .map_err(|e| format!("failed to write stable memory: {}", e))
+}
+
+// None gdy stable memory jest puste (pierwsza instalacja)
+pub fn restore() -> Result<Option<StableState>, String> {
+    if stable_size() == 0 {
+        return Ok(None);
+    }
+
+    let mut reader = Reader { offset: 0, capacity: stable_size() * WASM_PAGE_SIZE };
+    let magic: [u8; 4] = reader.array()?;
+    if &magic != STATE_MAGIC {
+        return Err("stable memory does not contain canister state".to_string());
+    }
+    let version = u32::from_le_bytes(reader.array()?);
+    if version != STATE_VERSION {
+        return Err(format!(
+            "unsupported stable state version {} (this build reads {})",
+            version, STATE_VERSION
+        ));
+    }
+
+    // Własny zapis z save(): pełny Valid::check przeliczyłby każdy węzeł i przy
+    // dużym drzewie nie zmieściłby się w limicie instrukcji post_upgrade
+    let tree_bytes = reader.section()?;
+    let tree = from_trusted_bytes(&tree_bytes)
+        .map_err(|e| format!("failed to deserialize tree: {}", e))?;
+    let registry_bytes = reader.section()?;
+    let registrations = candid::decode_one(&registry_bytes)
+        .map_err(|e| format!("failed to decode circuit registry: {}", e))?;
+    let roles_bytes = reader.section()?;
+    let roles =
+        candid::decode_one(&roles_bytes).map_err(|e| format!("failed to decode roles: {}", e))?;
+    Ok(Some(StableState { tree, registrations, roles }))
+}
+
+struct Reader {
+    offset: u64,
+    capacity: u64,
+}
+
+impl Reader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        let end = self.offset.saturating_add(buf.len() as u64);
+        if end > self.capacity {
+            return Err("stable state is truncated".to_string());
+        }
+        stable_read(self.offset, buf);
+        self.offset = end;
+        Ok(())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut buf = [0u8; N];
+        self.read(&mut buf)?;
+        Ok(buf)
+    }
+
+    // Sekcja poprzedzona długością u64
+    fn section(&mut self) -> Result<Vec<u8>, String> {
+        let len = u64::from_le_bytes(self.array()?);
+        if self.offset.saturating_add(len) > self.capacity {
+            return Err("stable state is truncated".to_string());
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.read(&mut buf)?;
+        Ok(buf)
+    }
+}
@@ -0,0 +1,65 @@
+// Statystyki dla dashboardu monitoringu. Liczniki i czas ostatniego zapisu są
+// tylko w pamięci, więc po upgradzie liczą się od zera. Query nie zapisuje
+// stanu, dlatego liczone są wyłącznie wywołania update.
+
+use candid::CandidType;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use zk_smt::field_to_bytes;
+
+use crate::stable::WASM_PAGE_SIZE;
+use crate::RegistryTree;
+
+thread_local! {
+    static CALLS: RefCell<BTreeMap<&'static str, u64>> = const { RefCell::new(BTreeMap::new()) };
+    static LAST_MUTATION: RefCell<Option<u64>> = const { RefCell::new(None) };
+}
+
+#[derive(Serialize, CandidType)]
+pub struct CallCount {
+    pub endpoint: String,
+    pub calls: u64,
+}
+
+#[derive(Serialize, CandidType)]
+pub struct SmtStats {
+    pub entry_count: u64,
+    pub root: String,
+    pub version: u64,
+    pub node_count: u64,
+    pub stable_memory_bytes: u64,
+    pub cycle_balance: u128,
+    // Nanosekundy od epoki (ic_cdk::api::time); None jeśli od upgrade'u nie było zapisu
+    pub last_mutation_ns: Option<u64>,
+    pub calls: Vec<CallCount>,
+}
+
+pub fn count_call(endpoint: &'static str) {
+    CALLS.with(|c| *c.borrow_mut().entry(endpoint).or_insert(0) += 1);
+}
+
+pub fn record_mutation() {
+    LAST_MUTATION.with(|m| *m.borrow_mut() = Some(ic_cdk::api::time()));
+}
+
+pub fn collect(tree: &RegistryTree) -> SmtStats {
+    SmtStats {
+        entry_count: tree.data.len() as u64,
+        root: hex::encode(field_to_bytes(tree.root())),
+        version: tree.version(),
+        node_count: tree.node_count() as u64,
+        stable_memory_bytes: ic_cdk::api::stable::stable_size() * WASM_PAGE_SIZE,
+        cycle_balance: ic_cdk::api::canister_balance128(),
+        last_mutation_ns: LAST_MUTATION.with(|m| *m.borrow()),
+        calls: CALLS.with(|c| {
+            c.borrow()
+                .iter()
+                .map(|(endpoint, calls)| CallCount {
+                    endpoint: endpoint.to_string(),
+                    calls: *calls,
+                })
+                .collect()
+        }),
+    }
+}
@@ -6,6 +6,18 @@ console.log('App.jsx loading...');
 console.log('ss1_backend:', ss1_backend);
 console.log('createActor:', createActor);
 
+// Backend errors (VerifyError, ApiError) are Candid variants, e.g. { UnknownCircuit: "zk_membership" }
+const formatBackendError = (error) => {
+  const [kind, detail] = Object.entries(error)[0];
+  if (detail === null || detail === '') {
+    return kind;
+  }
+  if (typeof detail === 'object') {
+    return `${kind} ${JSON.stringify(detail, (_, v) => typeof v === 'bigint' ? v.toString() : v)}`;
+  }
+  return `${kind}: ${detail}`;
+};
+
 function App() {
   console.log('App component rendering...');
   const [name, setName] = useState('');
@@ -75,14 +87,11 @@ function App() {
       }
       
       // Call backend function
-      const proofHex = await backend.generate_zk_proof_for_user(zkUsername, nonceNum);
-      
-      // Check if it's an error
-      if (proofHex.startsWith('4552524f52')) { // "ERROR" in hex
-        const errorBytes = new Uint8Array(proofHex.match(/.{1,2}/g).map(byte => parseInt(byte, 16)));
-        const errorText = new TextDecoder().decode(errorBytes);
-        throw new Error(errorText);
+      const response = await backend.generate_zk_proof_for_user(zkUsername, nonceNum);
+      if ('Err' in response) {
+        throw new Error(formatBackendError(response.Err));
       }
+      const proofHex = response.Ok;
       
       setZkProof(proofHex);
       
@@ -144,30 +153,34 @@ Proof (hex): \${proofHex}
       let userValue = 0; // Default for non-membership
       let isUserInSMT = false;
       
-      try {
-        merkleProof = await backend.get_merkle_proof(zkUsername);
+      const proofResponse = await backend.get_merkle_proof(zkUsername);
+      if ('Ok' in proofResponse) {
+        merkleProof = proofResponse.Ok;
         console.log('Merkle proof received:', merkleProof);
         isUserInSMT = true;
-        
+      } else if ('KeyNotFound' in proofResponse.Err) {
+        console.log('User not found in SMT - will generate non-membership proof');
+      } else {
+        throw new Error(formatBackendError(proofResponse.Err));
+      }
+      
+      if (isUserInSMT) {
         // Step 2.5: Get the actual user value from SMT entries
+        // (the first key with the username as prefix is the username itself, if registered)
         console.log('🔍 Getting user value from SMT...');
-        const allEntries = await backend.get_all_smt_entries();
-        console.log('All SMT entries:', allEntries);
-        
-        const userEntry = allEntries.find(([key, value]) => key === zkUsername);
+        const page = await backend.list_keys_with_prefix(zkUsername, [], 1n);
+        const userEntry = page.entries.find((entry) => entry.key === zkUsername);
+        if (userEntry && userEntry.value.length === 0) {
+          throw new Error('Reading user IDs requires the Reader role');
+        }
         if (userEntry) {
-          userValue = parseInt(userEntry[1]);
+          userValue = Number(userEntry.value[0]);
           console.log('User value from SMT:', userValue);
         } else {
           console.log('User not found in SMT entries despite having merkle proof');
           userValue = 0;
           isUserInSMT = false;
         }
-      } catch (proofError) {
-        console.log('User not found in SMT - will generate non-membership proof');
-        merkleProof = null;
-        userValue = 0;
-        isUserInSMT = false;
       }
       
       // Step 3: Calculate publicKey from username (consistent with backend)
@@ -372,8 +385,12 @@ Debug info: ${error.stack || 'No stack trace available'}`);
         throw new Error('Backend canister is not available.');
       }
       
-      const result = await backend.insert(key, idValue);
-      setOutput(`Inserted: ${key} -> ${value} (id: ${idValue})\nResult: ${result}`);
+      const response = await backend.insert(key, idValue);
+      if ('Err' in response) {
+        throw new Error(formatBackendError(response.Err));
+      }
+      const result = response.Ok;
+      setOutput(`Inserted: ${key} -> ${value} (id: ${idValue})\nRoot: ${result.old_root} -> ${result.new_root}`);
     } catch (error) {
       setOutput(`Error inserting: ${error.message}`);
     }
@@ -389,8 +406,12 @@ Debug info: ${error.stack || 'No stack trace available'}`);
         throw new Error('Backend canister is not available.');
       }
       
-      const result = await backend.delete(key);
-      setOutput(`Deleted: ${key}\nResult: ${result}`);
+      const response = await backend.delete(key);
+      if ('Err' in response) {
+        throw new Error(formatBackendError(response.Err));
+      }
+      const result = response.Ok;
+      setOutput(`Deleted: ${key}\nRoot: ${result.old_root} -> ${result.new_root}`);
     } catch (error) {
       setOutput(`Error deleting: ${error.message}`);
     }
@@ -406,8 +427,11 @@ Debug info: ${error.stack || 'No stack trace available'}`);
         throw new Error('Backend canister is not available.');
       }
       
-      const proof = await backend.get_merkle_proof(key);
-      setOutput(`Merkle Proof for ${key}:\n${JSON.stringify(proof, null, 2)}`);
+      const response = await backend.get_merkle_proof(key);
+      if ('Err' in response) {
+        throw new Error(formatBackendError(response.Err));
+      }
+      setOutput(`Merkle Proof for ${key}:\n${JSON.stringify(response.Ok, null, 2)}`);
     } catch (error) {
       setOutput(`Error getting proof: ${error.message}`);
     }
@@ -459,7 +483,9 @@ Debug info: ${error.stack || 'No stack trace available'}`);
       const result = await backend.verify_zk_membership(verifyRequest);
       console.log('Verification result:', result);
       
-      setOutput(`ZK Proof Verification Result: ${result ? 'VALID' : 'INVALID'}`);
+      setOutput('Ok' in result
+        ? 'ZK Proof Verification Result: VALID'
+        : `ZK Proof Verification Result: INVALID (${formatBackendError(result.Err)})`);
     } catch (error) {
       console.error('Error during verification:', error);
       setOutput(`Error verifying ZK proof: ${error.message}`);
@@ -0,0 +1,157 @@
+// Many writes applied together. Leaves are set first and every affected
+// inner node is then rehashed once, so upper levels shared by the written
+// keys are not recomputed per key as with `insert_to_tree`.
+
+use ark_ff::PrimeField;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{hash_leaf, key_index, SparseMerkleTree, TreeEncoding, TreeHasher};
+
+/// Pending inserts and deletes; a later write to the same key replaces an
+/// earlier one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteBatch<K = String, V = String> {
+    ops: BTreeMap<K, Option<V>>,
+}
+
+impl<K, V> Default for WriteBatch<K, V> {
+    fn default() -> Self {
+        Self { ops: BTreeMap::new() }
+    }
+}
+
+impl<K: Ord, V> WriteBatch<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<Q, R>(&mut self, key: &Q, value: &R) -> &mut Self
+    where
+        Q: ToOwned<Owned = K> + ?Sized,
+        R: ToOwned<Owned = V> + ?Sized,
+    {
+        self.ops.insert(key.to_owned(), Some(value.to_owned()));
+        self
+    }
+
+    pub fn delete<Q: ToOwned<Owned = K> + ?Sized>(&mut self, key: &Q) -> &mut Self {
+        self.ops.insert(key.to_owned(), None);
+        self
+    }
+
+    /// Number of distinct keys written.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Applies every write in `batch` as a single tree version; the resulting
+/// root is the same as issuing them one by one with `insert_to_tree` /
+/// `delete_from_tree`.
+pub fn apply_batch<F, H, K, V>(tree: &mut SparseMerkleTree<F, H, K, V>, batch: &WriteBatch<K, V>)
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: TreeEncoding + Ord + Clone,
+    V: TreeEncoding + Clone,
+{
+    let writes: Vec<(&K, Option<&V>)> = batch
+        .ops
+        .iter()
+        .map(|(key, value)| (key, value.as_ref()))
+        .filter(|(key, value)| value.is_some() || tree.data.contains_key(*key))
+        .collect();
+    if writes.is_empty() {
+        return;
+    }
+
+    tree.begin_version();
+    let mut changed = BTreeSet::new();
+    for (key, value) in writes {
+        tree.set_data(key.clone(), value.cloned());
+        let leaf = value.map(|value| hash_leaf(&tree.hasher, key, value));
+        let index = key_index(&tree.hasher, key);
+        tree.set_leaf(&index, leaf);
+        changed.insert(index);
+    }
+    tree.rehash(changed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delete_from_tree, insert_to_tree, Fr, Sha256Hasher, TREE_DEPTH};
+    use std::cell::Cell;
+
+    /// Sha256Hasher that counts every hash it computes.
+    #[derive(Default)]
+    struct CountingHasher {
+        count: Cell<usize>,
+    }
+
+    impl TreeHasher<Fr> for CountingHasher {
+        fn hash_bytes(&self, bytes: &[u8]) -> Fr {
+            self.count.set(self.count.get() + 1);
+            Sha256Hasher.hash_bytes(bytes)
+        }
+
+        fn hash_two_to_one(&self, left: Fr, right: Fr) -> Fr {
+            self.count.set(self.count.get() + 1);
+            Sha256Hasher.hash_two_to_one(left, right)
+        }
+    }
+
+    #[test]
+    fn batch_root_matches_sequential_writes() {
+        let mut sequential = SparseMerkleTree::new();
+        let mut batched = SparseMerkleTree::new();
+        for i in 0..50 {
+            insert_to_tree(&mut sequential, format!("user{}", i).as_str(), "1");
+            insert_to_tree(&mut batched, format!("user{}", i).as_str(), "1");
+        }
+
+        let mut batch = WriteBatch::new();
+        for i in 0..100 {
+            let key = format!("user{}", i);
+            if i % 3 == 0 {
+                delete_from_tree(&mut sequential, key.as_str());
+                batch.delete(key.as_str());
+            } else {
+                insert_to_tree(&mut sequential, key.as_str(), "2");
+                batch.insert(key.as_str(), "2");
+            }
+        }
+        apply_batch(&mut batched, &batch);
+
+        assert_eq!(batched.root(), sequential.root());
+        assert_eq!(batched.data, sequential.data);
+        assert_eq!(batched.version(), 51);
+    }
+
+    #[test]
+    fn batch_of_new_keys_stays_within_hash_budget() {
+        let mut tree = SparseMerkleTree::<Fr, CountingHasher>::with_hasher(Default::default());
+        let mut batch = WriteBatch::new();
+        for i in 0..1000 {
+            batch.insert(format!("user{}", i).as_str(), "1");
+        }
+        apply_batch(&mut tree, &batch);
+
+        let mut batch = WriteBatch::new();
+        for i in 1000..1500 {
+            batch.insert(format!("user{}", i).as_str(), "1");
+        }
+        tree.hasher().count.set(0);
+        apply_batch(&mut tree, &batch);
+
+        // Key, value and leaf, the path to the root, and one single-key
+        // subtree recomputed where the new key branches off
+        let per_write = 3 + TREE_DEPTH + (TREE_DEPTH - 1);
+        let count = tree.hasher().count.get();
+        assert!(count <= per_write * batch.len(), "{} hashes", count);
+    }
+}
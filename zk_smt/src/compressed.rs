@@ -0,0 +1,260 @@
+// Membership proofs with the empty-subtree siblings left out. In a sparse
+// tree almost every sibling near the root is a default hash, so a proof for a
+// tree of n keys carries roughly log2(n) siblings instead of 256.
+
+use ark_bls12_381::Fr;
+use ark_ff::PrimeField;
+use ark_std::vec::Vec;
+use std::borrow::Borrow;
+
+use crate::{
+    hash_leaf, index_bit, key_index, MerkleTreePath, PathElement, Sha256Hasher, SparseMerkleTree,
+    expect_root, Error, TreeEncoding, TreeHasher, TREE_DEPTH,
+};
+
+const BITMAP_MISMATCH: Error = Error::MalformedProof("bitmap does not match the stored siblings");
+
+/// Bit `h` of `bitmap` (little-endian, like leaf indices) is set when the
+/// sibling at height `h` differs from `default_hashes[h]`; those siblings are
+/// kept in `siblings`, leaf to root. Directions come from the key, so they are
+/// not stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedMerkleTreePath<F: PrimeField = Fr> {
+    pub bitmap: [u8; 32],
+    pub siblings: Vec<F>,
+}
+
+impl<F: PrimeField> CompressedMerkleTreePath<F> {
+    /// Fails unless `path` has one sibling per level.
+    pub fn compress(path: &MerkleTreePath<F>, default_hashes: &[F]) -> Result<Self, Error> {
+        if path.path.len() != TREE_DEPTH {
+            return Err(Error::WrongPathLength { expected: TREE_DEPTH, actual: path.path.len() });
+        }
+
+        let mut bitmap = [0u8; 32];
+        let mut siblings = Vec::new();
+        for (height, element) in path.path.iter().enumerate() {
+            if element.value != default_hashes[height] {
+                bitmap[height / 8] |= 1 << (height % 8);
+                siblings.push(element.value);
+            }
+        }
+        Ok(Self { bitmap, siblings })
+    }
+
+    /// Restores the full path for `key`, recomputing the default siblings
+    /// with `hasher`. Fails if the bitmap and siblings disagree.
+    pub fn decompress<H, Q>(&self, hasher: &H, key: &Q) -> Result<MerkleTreePath<F>, Error>
+    where
+        H: TreeHasher<F>,
+        Q: TreeEncoding + ?Sized,
+    {
+        let index = key_index(hasher, key);
+        let path = self
+            .expand(hasher)?
+            .into_iter()
+            .enumerate()
+            .map(|(height, value)| PathElement {
+                value,
+                is_left: index_bit(&index, height),
+            })
+            .collect();
+        Ok(MerkleTreePath { path })
+    }
+
+    /// Number of siblings the bitmap says are stored.
+    pub fn stored_count(&self) -> usize {
+        self.bitmap.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+
+    /// All `TREE_DEPTH` siblings, leaf to root.
+    fn expand<H: TreeHasher<F>>(&self, hasher: &H) -> Result<Vec<F>, Error> {
+        if self.siblings.len() != self.stored_count() {
+            return Err(BITMAP_MISMATCH);
+        }
+
+        let mut stored = self.siblings.iter();
+        let mut default = F::zero();
+        let mut siblings = Vec::with_capacity(TREE_DEPTH);
+        for height in 0..TREE_DEPTH {
+            siblings.push(if index_bit(&self.bitmap, height) {
+                *stored.next().ok_or(BITMAP_MISMATCH)?
+            } else {
+                default
+            });
+            default = hasher.hash_two_to_one(default, default);
+        }
+        Ok(siblings)
+    }
+}
+
+/// Compressed form of [`get_merkle_path`](crate::get_merkle_path); fails
+/// with [`Error::KeyNotFound`] when `key` is not in the tree.
+pub fn get_compressed_merkle_path<F, H, K, V, Q>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    key: &Q,
+) -> Result<CompressedMerkleTreePath<F>, Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Borrow<Q>,
+    Q: TreeEncoding + Ord + ?Sized,
+{
+    if !tree.data.contains_key(key) {
+        return Err(Error::KeyNotFound);
+    }
+
+    let path = tree.sibling_path(&key_index(&tree.hasher, key));
+    CompressedMerkleTreePath::compress(&path, &tree.default_hashes)
+}
+
+/// Checks that `(key, value)` is a leaf of the SHA-256 tree with the given `root`.
+pub fn verify_compressed_membership<Q, R>(
+    root: Fr,
+    key: &Q,
+    value: &R,
+    proof: &CompressedMerkleTreePath,
+) -> Result<(), Error>
+where
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    verify_compressed_membership_with(&Sha256Hasher, root, key, value, proof)
+}
+
+/// Folds the leaf up without expanding the proof, filling in default
+/// siblings as it goes.
+pub fn verify_compressed_membership_with<F, H, Q, R>(
+    hasher: &H,
+    root: F,
+    key: &Q,
+    value: &R,
+    proof: &CompressedMerkleTreePath<F>,
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    let leaf = hash_leaf(hasher, key, value);
+    expect_root(root_from_compressed_path(hasher, &key_index(hasher, key), leaf, proof)?, root)
+}
+
+/// Compressed counterpart of `root_from_path`; fails if the bitmap and
+/// siblings disagree.
+pub(crate) fn root_from_compressed_path<F: PrimeField, H: TreeHasher<F>>(
+    hasher: &H,
+    index: &[u8; 32],
+    leaf: F,
+    proof: &CompressedMerkleTreePath<F>,
+) -> Result<F, Error> {
+    if proof.siblings.len() != proof.stored_count() {
+        return Err(BITMAP_MISMATCH);
+    }
+
+    let mut stored = proof.siblings.iter();
+    let mut default = F::zero();
+    let mut current = leaf;
+    for height in 0..TREE_DEPTH {
+        let sibling = if index_bit(&proof.bitmap, height) {
+            *stored.next().ok_or(BITMAP_MISMATCH)?
+        } else {
+            default
+        };
+        current = if index_bit(index, height) {
+            hasher.hash_two_to_one(sibling, current)
+        } else {
+            hasher.hash_two_to_one(current, sibling)
+        };
+        default = hasher.hash_two_to_one(default, default);
+    }
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tree_with_keys;
+    use crate::{get_merkle_path, get_non_membership_proof};
+
+    #[test]
+    fn compress_and_decompress_round_trip() {
+        let tree = tree_with_keys(30);
+        for key in ["user0", "user17", "user29"] {
+            let path = get_merkle_path(&tree, key).unwrap();
+            let compressed = get_compressed_merkle_path(&tree, key).unwrap();
+            let expected = CompressedMerkleTreePath::compress(&path, &tree.default_hashes);
+            assert_eq!(Ok(compressed.clone()), expected);
+            assert_eq!(compressed.siblings.len(), compressed.stored_count());
+            assert!(compressed.siblings.len() < 16);
+            assert_eq!(compressed.decompress(tree.hasher(), key).unwrap(), path);
+        }
+
+        // A lone key has only default siblings
+        let tree = tree_with_keys(1);
+        let compressed = get_compressed_merkle_path(&tree, "user0").unwrap();
+        assert_eq!(compressed.bitmap, [0u8; 32]);
+        assert!(compressed.siblings.is_empty());
+        let path = get_merkle_path(&tree, "user0");
+        assert_eq!(compressed.decompress(tree.hasher(), "user0"), path);
+    }
+
+    #[test]
+    fn compress_requires_a_full_path() {
+        let tree = tree_with_keys(3);
+        let mut path = get_merkle_path(&tree, "user1").unwrap();
+        path.path.pop();
+        assert_eq!(
+            CompressedMerkleTreePath::compress(&path, &tree.default_hashes),
+            Err(Error::WrongPathLength { expected: TREE_DEPTH, actual: TREE_DEPTH - 1 })
+        );
+    }
+
+    #[test]
+    fn verifies_present_keys_only() {
+        let tree = tree_with_keys(30);
+        let proof = get_compressed_merkle_path(&tree, "user5").unwrap();
+        assert_eq!(verify_compressed_membership(tree.root(), "user5", "5", &proof), Ok(()));
+        assert_eq!(
+            verify_compressed_membership(tree.root(), "user5", "6", &proof),
+            Err(Error::RootMismatch)
+        );
+        assert_eq!(
+            verify_compressed_membership(tree.root(), "user6", "6", &proof),
+            Err(Error::RootMismatch)
+        );
+
+        // An absent key has no membership proof, and its empty slot does not
+        // verify as holding any value
+        assert_eq!(get_compressed_merkle_path(&tree, "nobody"), Err(Error::KeyNotFound));
+        let slot = get_non_membership_proof(&tree, "nobody").unwrap().path;
+        let slot = CompressedMerkleTreePath::compress(&slot, &tree.default_hashes).unwrap();
+        assert_eq!(
+            verify_compressed_membership(tree.root(), "nobody", "0", &slot),
+            Err(Error::RootMismatch)
+        );
+    }
+
+    #[test]
+    fn rejects_a_bitmap_that_disagrees_with_the_siblings() {
+        let tree = tree_with_keys(30);
+        let proof = get_compressed_merkle_path(&tree, "user5").unwrap();
+
+        let mut extra = proof.clone();
+        extra.siblings.push(Fr::from(1u64));
+        let mut missing = proof.clone();
+        missing.siblings.pop();
+        let mut unset = proof.clone();
+        let height = (0..TREE_DEPTH).find(|h| index_bit(&proof.bitmap, *h)).unwrap();
+        unset.bitmap[height / 8] &= !(1 << (height % 8));
+
+        for bad in [extra, missing, unset] {
+            assert_eq!(
+                verify_compressed_membership(tree.root(), "user5", "5", &bad),
+                Err(BITMAP_MISMATCH)
+            );
+            assert_eq!(bad.decompress(tree.hasher(), "user5"), Err(BITMAP_MISMATCH));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// How keys and values enter the tree. A key's leaf index and a leaf's hash
+// are both computed from `to_field`, which is `hasher.hash_bytes(encode())`
+// except for field elements, so the encoding of a type fixes the roots it
+// produces.
+
+use ark_ff::PrimeField;
+use std::borrow::Cow;
+
+use crate::{field_byte_len, field_to_bytes, TreeHasher};
+
+/// Canonical byte form of a key or value. Types that borrow as one another,
+/// like `String` and `str`, must encode identically, as with `Hash` and `Eq`.
+pub trait TreeEncoding {
+    fn encode(&self) -> Cow<'_, [u8]>;
+
+    /// The field element the tree hashes for this key or value.
+    fn to_field<F: PrimeField, H: TreeHasher<F>>(&self, hasher: &H) -> F {
+        hasher.hash_bytes(&self.encode())
+    }
+}
+
+/// Inverse of [`TreeEncoding::encode`], needed to read a stored tree back.
+/// Returns `None` for bytes `encode` never produces.
+pub trait TreeDecoding: TreeEncoding + Sized {
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+/// UTF-8 bytes, the tree's original encoding.
+impl TreeEncoding for str {
+    fn encode(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl TreeEncoding for String {
+    fn encode(&self) -> Cow<'_, [u8]> {
+        self.as_str().encode()
+    }
+}
+
+impl TreeDecoding for String {
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+/// Decimal digits, so a number hashes exactly like its `to_string()` did
+/// when values were stored as strings.
+impl TreeEncoding for u64 {
+    fn encode(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.to_string().into_bytes())
+    }
+}
+
+impl TreeDecoding for u64 {
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let value: u64 = std::str::from_utf8(bytes).ok()?.parse().ok()?;
+        // Odrzuć "+1", "007" itp. - nie są kanoniczne
+        (value.encode().as_ref() == bytes).then_some(value)
+    }
+}
+
+/// Raw bytes, for binary identifiers such as public keys.
+impl TreeEncoding for [u8; 32] {
+    fn encode(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl TreeDecoding for [u8; 32] {
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+macro_rules! impl_field_encoding {
+    ($($field:ty),*) => {
+        $(
+            /// Little-endian bytes, as [`field_to_bytes`].
+            impl TreeEncoding for $field {
+                fn encode(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(field_to_bytes(*self))
+                }
+
+                /// The element itself, reduced into `F`, so a leaf over
+                /// Poseidon is `Poseidon(key, value)` as a circuit computes it.
+                fn to_field<F: PrimeField, H: TreeHasher<F>>(&self, _hasher: &H) -> F {
+                    F::from_le_bytes_mod_order(&field_to_bytes(*self))
+                }
+            }
+
+            /// Rejects values at or above the modulus.
+            impl TreeDecoding for $field {
+                fn decode(bytes: &[u8]) -> Option<Self> {
+                    if bytes.len() != field_byte_len::<$field>() {
+                        return None;
+                    }
+                    let value = <$field>::from_le_bytes_mod_order(bytes);
+                    (field_to_bytes(value) == bytes).then_some(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_field_encoding!(ark_bls12_381::Fr, ark_bn254::Fr);
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+    use ark_ff::PrimeField;
+    use std::str::FromStr;
+
+    use crate::{hash_leaf, PoseidonHasher, Sha256Hasher, TreeEncoding};
+
+    #[test]
+    fn field_leaf_is_circom_poseidon_of_key_and_value() {
+        let leaf: Fr = hash_leaf(&PoseidonHasher::new(), &Fr::from(1u64), &Fr::from(2u64));
+        let expected = Fr::from_str(
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+        )
+        .unwrap();
+        assert_eq!(leaf, expected);
+    }
+
+    #[test]
+    fn field_elements_are_reduced_into_the_tree_field() {
+        let value = -ark_bls12_381::Fr::from(1u64);
+        let reduced: Fr = value.to_field(&Sha256Hasher);
+        assert_eq!(reduced, Fr::from_le_bytes_mod_order(&crate::field_to_bytes(value)));
+        assert_eq!(Fr::from(7u64).to_field::<Fr, _>(&Sha256Hasher), Fr::from(7u64));
+    }
+}
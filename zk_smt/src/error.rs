@@ -0,0 +1,59 @@
+// Reasons a proof or an encoded value is rejected. Verifiers return these
+// instead of a bare `false` so callers can tell a stale root from a forged
+// path.
+
+use ark_serialize::SerializationError;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A full path without exactly one sibling per tree level.
+    WrongPathLength { expected: usize, actual: usize },
+    /// A path element whose left/right flag disagrees with the key's index.
+    PathDirectionMismatch { height: usize },
+    /// A proof that is inconsistent with itself, e.g. a bitmap that does not
+    /// match the number of siblings.
+    MalformedProof(&'static str),
+    /// A well-formed proof that leads to a different root.
+    RootMismatch,
+    /// A root that is neither current nor within the accepted history window.
+    UnknownRoot,
+    KeyNotFound,
+    /// Bytes or snarkjs JSON that do not decode to the expected value.
+    BadEncoding(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::WrongPathLength { expected, actual } => {
+                write!(f, "path has {} siblings, expected {}", actual, expected)
+            }
+            Error::PathDirectionMismatch { height } => {
+                write!(f, "path direction at height {} does not match the key", height)
+            }
+            Error::MalformedProof(reason) => write!(f, "malformed proof: {}", reason),
+            Error::RootMismatch => write!(f, "proof does not lead to the expected root"),
+            Error::UnknownRoot => write!(f, "root is not current or recent"),
+            Error::KeyNotFound => write!(f, "key not found"),
+            Error::BadEncoding(reason) => write!(f, "bad encoding: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Self {
+        Error::BadEncoding(e.to_string())
+    }
+}
+
+/// `Ok` when a recomputed root is the one the proof was checked against.
+pub(crate) fn expect_root<F: PartialEq>(computed: F, root: F) -> Result<(), Error> {
+    if computed == root {
+        Ok(())
+    } else {
+        Err(Error::RootMismatch)
+    }
+}
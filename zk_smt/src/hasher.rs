@@ -0,0 +1,131 @@
+use ark_bls12_381::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use sha2::{Digest, Sha256};
+
+use crate::poseidon::Poseidon;
+
+/// Hash function used for keys, values and internal nodes of a tree over `F`.
+pub trait TreeHasher<F: PrimeField> {
+    /// Hashes arbitrary bytes (keys and values) into a field element.
+    fn hash_bytes(&self, bytes: &[u8]) -> F;
+
+    fn hash_two_to_one(&self, left: F, right: F) -> F;
+}
+
+/// SHA-256 reduced modulo the scalar field; the tree's original hash.
+#[derive(Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+impl<F: PrimeField> TreeHasher<F> for Sha256Hasher {
+    fn hash_bytes(&self, bytes: &[u8]) -> F {
+        let mut hasher = Sha256::new();
+        hasher.update(bytes);
+        let result = hasher.finalize();
+        F::from_le_bytes_mod_order(&result[..])
+    }
+
+    fn hash_two_to_one(&self, left: F, right: F) -> F {
+        let mut hasher = Sha256::new();
+        hasher.update(left.into_bigint().to_bytes_le());
+        hasher.update(right.into_bigint().to_bytes_le());
+        let result = hasher.finalize();
+        F::from_le_bytes_mod_order(&result[..])
+    }
+}
+
+/// BLAKE3 reduced modulo the scalar field.
+#[derive(Clone, Copy, Default)]
+pub struct Blake3Hasher;
+
+impl<F: PrimeField> TreeHasher<F> for Blake3Hasher {
+    fn hash_bytes(&self, bytes: &[u8]) -> F {
+        F::from_le_bytes_mod_order(blake3::hash(bytes).as_bytes())
+    }
+
+    fn hash_two_to_one(&self, left: F, right: F) -> F {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&left.into_bigint().to_bytes_le());
+        hasher.update(&right.into_bigint().to_bytes_le());
+        F::from_le_bytes_mod_order(hasher.finalize().as_bytes())
+    }
+}
+
+/// circomlib's `Poseidon(2)` construction with constants derived for `F`;
+/// over BN254 it is bit-identical to the Circom gadget.
+#[derive(Clone)]
+pub struct PoseidonHasher<F: PrimeField = Fr> {
+    poseidon: Poseidon<F>,
+}
+
+impl<F: PrimeField> PoseidonHasher<F> {
+    pub fn new() -> Self {
+        Self {
+            poseidon: Poseidon::circom(2),
+        }
+    }
+}
+
+impl<F: PrimeField> Default for PoseidonHasher<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: PrimeField> TreeHasher<F> for PoseidonHasher<F> {
+    /// Packs the bytes into 31-byte little-endian chunks, which always fit
+    /// in the field, and absorbs them one at a time starting from the length.
+    /// Empty input absorbs a single empty chunk, so the bare seed is never returned.
+    fn hash_bytes(&self, bytes: &[u8]) -> F {
+        let empty: &[u8] = &[];
+        bytes
+            .chunks(31)
+            .chain(bytes.is_empty().then_some(empty))
+            .fold(F::from(bytes.len() as u64), |acc, chunk| {
+                self.poseidon.hash(&[acc, F::from_le_bytes_mod_order(chunk)])
+            })
+    }
+
+    fn hash_two_to_one(&self, left: F, right: F) -> F {
+        self.poseidon.hash(&[left, right])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bls12_381::Fr;
+    use ark_ff::{PrimeField, Zero};
+
+    use super::*;
+    use crate::serialization::decode_hex;
+
+    // Known answers: the digest of "abc" reduced modulo the field
+    fn check(hasher: &impl TreeHasher<Fr>, expected_digest: &str) {
+        let digest = decode_hex(expected_digest).unwrap();
+        assert_eq!(hasher.hash_bytes(b"abc"), Fr::from_le_bytes_mod_order(&digest));
+    }
+
+    #[test]
+    fn sha256_matches_known_answer() {
+        check(
+            &Sha256Hasher,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        );
+    }
+
+    #[test]
+    fn blake3_matches_known_answer() {
+        check(
+            &Blake3Hasher,
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+        );
+    }
+
+    #[test]
+    fn poseidon_absorbs_empty_input() {
+        let hasher = PoseidonHasher::<Fr>::new();
+        let empty = hasher.hash_bytes(&[]);
+        assert_ne!(empty, Fr::zero());
+        assert_eq!(empty, hasher.hash_two_to_one(Fr::zero(), Fr::zero()));
+        assert_ne!(empty, hasher.hash_bytes(&[0]));
+    }
+}
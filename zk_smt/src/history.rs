@@ -0,0 +1,265 @@
+// Roots and proofs for recent versions of a tree. Each write starts a new
+// version and logs the leaves, stored nodes and entries it overwrites, so an
+// older version is read by looking a value up in the logs written since then
+// and falling back to the live tree.
+
+use ark_ff::PrimeField;
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+
+use crate::{
+    flip_bit, index_bit, key_index, node_key, sort_key, subtree_range, verify_membership_with,
+    MerkleTreePath, NodeKey, Error, PathElement, SparseMerkleTree, TreeEncoding, TreeHasher,
+    TREE_DEPTH,
+};
+
+/// Versions kept by a new tree.
+pub const DEFAULT_HISTORY_LIMIT: usize = 64;
+
+/// State of the tree at `version`: its root, and the leaves, stored nodes
+/// and entries the next write replaced, with their values as of `version`.
+/// `None` means the leaf, node or key was not stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootSnapshot<F: PrimeField, K = String, V = String> {
+    pub version: u64,
+    pub root: F,
+    // Keyed like the tree's leaves; rebuilt from `data` when deserializing.
+    pub(crate) leaves: BTreeMap<[u8; 32], Option<F>>,
+    pub(crate) nodes: BTreeMap<NodeKey, Option<F>>,
+    pub(crate) data: BTreeMap<K, Option<V>>,
+}
+
+impl<F: PrimeField, H: TreeHasher<F>, K: Ord, V> SparseMerkleTree<F, H, K, V> {
+    /// Retained snapshots, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &RootSnapshot<F, K, V>> {
+        self.history.iter()
+    }
+
+    /// Snapshots written at or after `version`, oldest first; `None` if
+    /// `version` is neither current nor retained.
+    fn snapshots_since(
+        &self,
+        version: u64,
+    ) -> Option<impl Iterator<Item = &RootSnapshot<F, K, V>>> {
+        let retained = version == self.version
+            || self.history.front().is_some_and(|oldest| oldest.version <= version)
+                && version < self.version;
+        retained.then(|| self.history.iter().filter(move |s| s.version >= version))
+    }
+
+    fn node_at(&self, height: usize, index: &[u8; 32], version: u64) -> Option<F> {
+        let key = node_key(index, height);
+        let stored = match self
+            .snapshots_since(version)?
+            .find_map(|snapshot| snapshot.nodes.get(&key).copied())
+        {
+            Some(logged) => logged,
+            None => self.nodes.get(&key).copied(),
+        };
+        match stored {
+            Some(value) => Some(value),
+            None => self.unstored_node_at(height, index, version),
+        }
+    }
+
+    /// [`Self::unstored_node`] over the leaves as of `version`.
+    fn unstored_node_at(&self, height: usize, index: &[u8; 32], version: u64) -> Option<F> {
+        let range = subtree_range(index, height);
+        let mut leaves: BTreeMap<[u8; 32], Option<F>> = self
+            .leaves
+            .range(range.clone())
+            .map(|(key, leaf)| (*key, Some(*leaf)))
+            .collect();
+        // Newest first, so the value logged right after `version` wins
+        let snapshots: Vec<_> = self.snapshots_since(version)?.collect();
+        for snapshot in snapshots.into_iter().rev() {
+            leaves.extend(snapshot.leaves.range(range.clone()));
+        }
+
+        let mut present = leaves.into_iter().filter_map(|(key, leaf)| Some((key, leaf?)));
+        Some(match (present.next(), present.next()) {
+            (None, _) => self.default_hashes[height],
+            (Some((key, leaf)), None) => self.fold_leaf(&sort_key(&key), leaf, height),
+            _ => {
+                let left = node_key(index, height).1;
+                let right = flip_bit(&left, height - 1);
+                self.hasher.hash_two_to_one(
+                    self.node_at(height - 1, &left, version)?,
+                    self.node_at(height - 1, &right, version)?,
+                )
+            }
+        })
+    }
+
+    /// Whether `key` was present at `version`; `None` if the version is
+    /// not retained.
+    fn contains_at<Q>(&self, key: &Q, version: u64) -> Option<bool>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let logged = self
+            .snapshots_since(version)?
+            .find_map(|snapshot| snapshot.data.get(key).map(Option::is_some));
+        Some(logged.unwrap_or_else(|| self.data.contains_key(key)))
+    }
+}
+
+/// Root of `tree` as of `version`, if that version is current or retained.
+pub fn get_root_at<F: PrimeField, H: TreeHasher<F>, K: Ord, V>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    version: u64,
+) -> Option<F> {
+    if version == tree.version {
+        return Some(tree.root);
+    }
+    tree.history
+        .iter()
+        .find(|snapshot| snapshot.version == version)
+        .map(|snapshot| snapshot.root)
+}
+
+/// Membership path of `key` against [`get_root_at`]`(tree, version)`. `None`
+/// if the version is not retained or the key was absent at that version.
+pub fn get_merkle_proof_at<F, H, K, V, Q>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    key: &Q,
+    version: u64,
+) -> Option<MerkleTreePath<F>>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Borrow<Q>,
+    Q: TreeEncoding + Ord + ?Sized,
+{
+    if !tree.contains_at(key, version)? {
+        return None;
+    }
+
+    let index = key_index(&tree.hasher, key);
+    let path = (0..TREE_DEPTH)
+        .map(|height| {
+            Some(PathElement {
+                value: tree.node_at(height, &flip_bit(&index, height), version)?,
+                is_left: index_bit(&index, height),
+            })
+        })
+        .collect::<Option<_>>()?;
+    Some(MerkleTreePath { path })
+}
+
+/// Whether `root` is the current root or one of the `window` before it.
+pub fn is_recent_root<F: PrimeField, H: TreeHasher<F>, K: Ord, V>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    root: F,
+    window: usize,
+) -> bool {
+    root == tree.root || tree.history.iter().rev().take(window).any(|s| s.root == root)
+}
+
+/// Like [`verify_membership_with`], but accepts a proof made against any
+/// root within `window` versions of the current one, so a proof built
+/// client-side survives concurrent writes.
+pub fn verify_membership_recent<F, H, K, V, Q, R>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    root: F,
+    key: &Q,
+    value: &R,
+    path: &MerkleTreePath<F>,
+    window: usize,
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord,
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    if !is_recent_root(tree, root, window) {
+        return Err(Error::UnknownRoot);
+    }
+    verify_membership_with(&tree.hasher, root, key, value, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_batch, delete_from_tree, get_merkle_path, insert_to_tree, WriteBatch};
+
+    /// Runs mixed writes with a short history, returning the tree and a copy
+    /// of it at every version. Writes that change nothing keep the version.
+    fn tree_with_copies(limit: usize) -> (SparseMerkleTree, BTreeMap<u64, SparseMerkleTree>) {
+        let mut tree = SparseMerkleTree::new();
+        tree.set_history_limit(limit);
+        let mut copies = BTreeMap::from([(0, tree.clone())]);
+        for i in 0..30 {
+            let key = format!("user{}", i % 12);
+            match i % 5 {
+                3 => delete_from_tree(&mut tree, key.as_str()),
+                4 => {
+                    let mut batch = WriteBatch::new();
+                    batch.insert(key.as_str(), "batched").delete("user0");
+                    apply_batch(&mut tree, &batch);
+                }
+                _ => insert_to_tree(&mut tree, key.as_str(), format!("{}", i).as_str()),
+            }
+            copies.insert(tree.version(), tree.clone());
+        }
+        (tree, copies)
+    }
+
+    #[test]
+    fn retained_versions_match_the_tree_as_it_was() {
+        let (tree, copies) = tree_with_copies(6);
+        assert_eq!(tree.history().count(), 6);
+
+        assert_eq!(copies.len() as u64, tree.version() + 1);
+        for (&version, copy) in &copies {
+            let retained = version + 6 >= tree.version();
+            let root = get_root_at(&tree, version);
+            assert_eq!(root, retained.then(|| copy.root()), "version {}", version);
+
+            for i in 0..13 {
+                let key = format!("user{}", i);
+                let path = get_merkle_proof_at(&tree, key.as_str(), version);
+                if retained {
+                    assert_eq!(path, get_merkle_path(copy, key.as_str()).ok());
+                } else {
+                    assert_eq!(path, None);
+                }
+                if let (Some(path), Some(value)) = (&path, copy.data.get(&key)) {
+                    let (root, key) = (copy.root(), key.as_str());
+                    let recent = verify_membership_recent(&tree, root, key, value, path, 6);
+                    assert_eq!(recent, Ok(()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lowering_the_limit_evicts_the_oldest_versions() {
+        let (mut tree, copies) = tree_with_copies(10);
+        let version = tree.version();
+        let old = &copies[&(version - 5)];
+        let key = old.data.keys().next().unwrap().clone();
+        let path = get_merkle_proof_at(&tree, key.as_str(), version - 5).unwrap();
+
+        tree.set_history_limit(3);
+        assert_eq!(tree.history().count(), 3);
+        assert_eq!(get_root_at(&tree, version - 4), None);
+        assert_eq!(get_merkle_proof_at(&tree, key.as_str(), version - 5), None);
+        assert!(get_root_at(&tree, version - 3).is_some());
+
+        let value = &old.data[&key];
+        assert_eq!(
+            verify_membership_recent(&tree, old.root(), key.as_str(), value, &path, 10),
+            Err(Error::UnknownRoot)
+        );
+
+        tree.set_history_limit(0);
+        insert_to_tree(&mut tree, "user1", "new");
+        assert_eq!(tree.history().count(), 0);
+        assert_eq!(get_root_at(&tree, tree.version() - 1), None);
+        assert_eq!(get_root_at(&tree, tree.version()), Some(tree.root()));
+    }
+}
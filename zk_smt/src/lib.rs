@@ -3,145 +3,674 @@
 use ark_bls12_381::Fr;
 use ark_ff::{PrimeField, BigInteger};
 use ark_std::vec::Vec;
-use std::collections::BTreeMap;
-use sha2::{Sha256, Digest};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::RangeInclusive;
+
+pub mod batch;
+pub mod compressed;
+pub mod encoding;
+pub mod error;
+pub mod hasher;
+pub mod history;
+pub mod multiproof;
+pub mod poseidon;
+pub mod serialization;
+pub mod snarkjs;
+pub mod update;
+
+#[cfg(test)]
+mod test_util;
+
+pub use batch::{apply_batch, WriteBatch};
+pub use compressed::{
+    get_compressed_merkle_path, verify_compressed_membership, verify_compressed_membership_with,
+    CompressedMerkleTreePath,
+};
+pub use encoding::{TreeDecoding, TreeEncoding};
+pub use error::Error;
+use error::expect_root;
+pub use hasher::{Blake3Hasher, PoseidonHasher, Sha256Hasher, TreeHasher};
+pub use history::{
+    get_merkle_proof_at, get_root_at, is_recent_root, verify_membership_recent, RootSnapshot,
+    DEFAULT_HISTORY_LIMIT,
+};
+pub use multiproof::{
+    get_multi_proof, verify_multi_proof, verify_multi_proof_with, MultiProof, MultiProofNode,
+};
+pub use update::{
+    compute_updated_root_with, delete_with_proof, insert_with_proof, verify_update_proof,
+    verify_update_proof_with, UpdateProof,
+};
 
 const TREE_DEPTH: usize = 256;
 
+/// Poseidon tree over BN254, matching circomlib's `Poseidon(2)` gadget. With
+/// `ark_bn254::Fr` keys and values a leaf is exactly `Poseidon(key, value)`.
+pub type Bn254PoseidonTree = SparseMerkleTree<ark_bn254::Fr, PoseidonHasher<ark_bn254::Fr>>;
+
+/// Position of a stored node: its height above the leaves and the leaf index
+/// with the low `height` bits cleared.
+type NodeKey = (usize, [u8; 32]);
+
+/// A tree over `F` hashed with `H`, mapping keys of type `K` to values of
+/// type `V`; see [`TreeEncoding`] for how keys and values are hashed.
+///
+/// Only nodes whose subtree holds at least two keys are stored, plus the top
+/// of every single-key subtree right below them. A node inside a single-key
+/// subtree is the key's leaf hashed with default siblings and is recomputed
+/// when needed, so a tree of n keys keeps O(n) nodes rather than 256 per key.
 #[derive(Clone)]
-pub struct SparseMerkleTree {
-    pub data: BTreeMap<String, String>,
-    pub root: Fr,
-    pub default_hashes: Vec<Fr>,
+pub struct SparseMerkleTree<
+    F: PrimeField = Fr,
+    H: TreeHasher<F> = Sha256Hasher,
+    K = String,
+    V = String,
+> {
+    pub data: BTreeMap<K, V>,
+    pub root: F,
+    pub default_hashes: Vec<F>,
+    // Leaf hashes by `sort_key(index)`, so each subtree is one range.
+    leaves: BTreeMap<[u8; 32], F>,
+    // Inner nodes whose parent covers two or more leaves; never a default hash.
+    nodes: BTreeMap<NodeKey, F>,
+    hasher: H,
+    // Bumped by every write; `history` keeps the last `history_limit` versions.
+    version: u64,
+    history_limit: usize,
+    history: VecDeque<RootSnapshot<F, K, V>>,
+}
+
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SparseMerkleTree {
     pub fn new() -> Self {
-        let mut default_hashes = vec![Fr::from(0u64); TREE_DEPTH + 1];
+        Self::with_hasher(Sha256Hasher)
+    }
+}
+
+impl<F: PrimeField, H: TreeHasher<F>, K: Ord, V> SparseMerkleTree<F, H, K, V> {
+    pub fn with_hasher(hasher: H) -> Self {
+        let mut default_hashes = vec![F::zero(); TREE_DEPTH + 1];
         
         // Pre-compute default hashes for empty subtrees
         for i in 0..TREE_DEPTH {
             let left = default_hashes[i];
             let right = default_hashes[i];
-            default_hashes[i + 1] = hash_two_to_one(left, right);
+            default_hashes[i + 1] = hasher.hash_two_to_one(left, right);
         }
         
         Self {
             data: BTreeMap::new(),
             root: default_hashes[TREE_DEPTH],
             default_hashes,
+            leaves: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+            hasher,
+            version: 0,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            history: VecDeque::new(),
         }
     }
 
-    pub fn root(&self) -> Fr {
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    pub fn root(&self) -> F {
         self.root
     }
 
-    fn compute_root(&mut self) {
-        if self.data.is_empty() {
-            self.root = Fr::from(0u64);
-            return;
+    /// Number of writes applied since the tree was created.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.history_limit
+    }
+
+    /// Sets how many past versions stay queryable; older ones are dropped.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
         }
+    }
 
-        // Simple but correct implementation:
-        // Hash all key-value pairs and combine them
-        let mut combined_hash = Fr::from(0u64);
-        
-        for (key, value) in &self.data {
-            let key_hash = hash_string(key);
-            let value_hash = hash_string(value);
-            let pair_hash = hash_two_to_one(key_hash, value_hash);
-            combined_hash = hash_two_to_one(combined_hash, pair_hash);
+    /// Number of leaves and inner nodes currently stored.
+    pub fn node_count(&self) -> usize {
+        self.leaves.len() + self.nodes.len()
+    }
+
+    fn node(&self, height: usize, index: &[u8; 32]) -> F {
+        if height == 0 {
+            return self.leaves.get(&sort_key(index)).copied().unwrap_or(self.default_hashes[0]);
         }
-        
-        self.root = combined_hash;
+        match self.nodes.get(&node_key(index, height)) {
+            Some(value) => *value,
+            None => self.unstored_node(height, index),
+        }
+    }
+
+    /// A node that is not in `nodes`: empty, or inside a single-key subtree.
+    fn unstored_node(&self, height: usize, index: &[u8; 32]) -> F {
+        let mut leaves = self.leaves.range(subtree_range(index, height));
+        match (leaves.next(), leaves.next()) {
+            (None, _) => self.default_hashes[height],
+            (Some((leaf_index, leaf)), None) => {
+                self.fold_leaf(&sort_key(leaf_index), *leaf, height)
+            }
+            // Only reached for trees restored without their stored nodes
+            _ => {
+                let left = node_key(index, height).1;
+                let right = flip_bit(&left, height - 1);
+                self.hasher
+                    .hash_two_to_one(self.node(height - 1, &left), self.node(height - 1, &right))
+            }
+        }
+    }
+
+    /// The node at `height` above a leaf whose subtree holds nothing else.
+    fn fold_leaf(&self, index: &[u8; 32], leaf: F, height: usize) -> F {
+        (0..height).fold(leaf, |current, level| {
+            let default = self.default_hashes[level];
+            if index_bit(index, level) {
+                self.hasher.hash_two_to_one(default, current)
+            } else {
+                self.hasher.hash_two_to_one(current, default)
+            }
+        })
+    }
+
+    /// Number of leaves under the node, counted up to two.
+    fn leaf_count(&self, height: usize, index: &[u8; 32]) -> usize {
+        self.leaves.range(subtree_range(index, height)).take(2).count()
     }
+
+    /// Rehashes every ancestor of the leaves at `indices` once per level,
+    /// after the leaves themselves have been written, and updates which of
+    /// the children of those ancestors are stored.
+    fn rehash(&mut self, indices: BTreeSet<[u8; 32]>) {
+        let mut level: BTreeMap<[u8; 32], F> =
+            indices.iter().map(|index| (*index, self.node(0, index))).collect();
+        for height in 0..TREE_DEPTH {
+            let parents: BTreeSet<[u8; 32]> =
+                level.keys().map(|index| node_key(index, height + 1).1).collect();
+            let mut next = BTreeMap::new();
+            for parent in parents {
+                let children = [parent, flip_bit(&parent, height)];
+                let [left, right] = children.map(|child| match level.get(&child) {
+                    Some(value) => *value,
+                    None => self.node(height, &child),
+                });
+                let value = self.hasher.hash_two_to_one(left, right);
+                if height > 0 {
+                    let shared = self.leaf_count(height + 1, &parent) > 1;
+                    for (child, child_value) in children.iter().zip([left, right]) {
+                        let stored = shared && child_value != self.default_hashes[height];
+                        self.set_node(height, child, stored.then_some(child_value));
+                    }
+                }
+                next.insert(parent, value);
+            }
+            level = next;
+        }
+        self.root = level.into_values().next().unwrap_or(self.root);
+    }
+
+    fn sibling_path(&self, index: &[u8; 32]) -> MerkleTreePath<F> {
+        let path = (0..TREE_DEPTH)
+            .map(|height| PathElement {
+                value: self.node(height, &flip_bit(index, height)),
+                is_left: index_bit(index, height),
+            })
+            .collect();
+        MerkleTreePath { path }
+    }
+
+    /// Starts a new version, snapshotting the current root. Leaf, node and
+    /// data writes that follow record what they replace in that snapshot.
+    fn begin_version(&mut self) {
+        if self.history_limit > 0 {
+            self.history.push_back(RootSnapshot {
+                version: self.version,
+                root: self.root,
+                leaves: BTreeMap::new(),
+                nodes: BTreeMap::new(),
+                data: BTreeMap::new(),
+            });
+            if self.history.len() > self.history_limit {
+                self.history.pop_front();
+            }
+        }
+        self.version += 1;
+    }
+
+    fn set_data(&mut self, key: K, value: Option<V>)
+    where
+        K: Clone,
+    {
+        let old = match value {
+            Some(value) => self.data.insert(key.clone(), value),
+            None => self.data.remove(&key),
+        };
+        if let Some(snapshot) = self.history.back_mut() {
+            snapshot.data.entry(key).or_insert(old);
+        }
+    }
+
+    /// Writes (`Some`) or clears (`None`) the leaf at `index`; call
+    /// [`Self::rehash`] afterwards.
+    fn set_leaf(&mut self, index: &[u8; 32], leaf: Option<F>) {
+        let key = sort_key(index);
+        let old = match leaf {
+            Some(leaf) => self.leaves.insert(key, leaf),
+            None => self.leaves.remove(&key),
+        };
+        if let Some(snapshot) = self.history.back_mut() {
+            snapshot.leaves.entry(key).or_insert(old);
+        }
+    }
+
+    fn set_node(&mut self, height: usize, index: &[u8; 32], value: Option<F>) {
+        let key = node_key(index, height);
+        let old = match value {
+            Some(value) => self.nodes.insert(key, value),
+            None => self.nodes.remove(&key),
+        };
+        if old != value {
+            if let Some(snapshot) = self.history.back_mut() {
+                snapshot.nodes.entry(key).or_insert(old);
+            }
+        }
+    }
+}
+
+/// Little-endian bytes of a field element, the encoding used for roots and
+/// siblings throughout the crate.
+pub fn field_to_bytes<F: PrimeField>(value: F) -> Vec<u8> {
+    value.into_bigint().to_bytes_le()
+}
+
+/// Inverse of [`field_to_bytes`]; out-of-range input is reduced.
+pub fn field_from_bytes<F: PrimeField>(bytes: &[u8]) -> F {
+    F::from_le_bytes_mod_order(bytes)
+}
+
+/// Decimal form of a field element, as used for snarkjs public signals.
+pub fn field_to_decimal<F: PrimeField>(value: F) -> String {
+    value.into_bigint().to_string()
+}
+
+/// Byte length of [`field_to_bytes`] output for `F`.
+pub fn field_byte_len<F: PrimeField>() -> usize {
+    field_to_bytes(F::zero()).len()
+}
+
+fn hash_leaf<F, H, Q, R>(hasher: &H, key: &Q, value: &R) -> F
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    hasher.hash_two_to_one(key.to_field(hasher), value.to_field(hasher))
+}
+
+/// Maps a key to its 256-bit leaf index (little-endian bit order).
+fn key_index<F, H, Q>(hasher: &H, key: &Q) -> [u8; 32]
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    Q: TreeEncoding + ?Sized,
+{
+    let mut index = [0u8; 32];
+    let bytes = field_to_bytes(key.to_field(hasher));
+    let len = bytes.len().min(32);
+    index[..len].copy_from_slice(&bytes[..len]);
+    index
 }
 
-fn hash_string(s: &str) -> Fr {
-    let mut hasher = Sha256::new();
-    hasher.update(s.as_bytes());
-    let result = hasher.finalize();
-    Fr::from_le_bytes_mod_order(&result[..])
+fn index_bit(index: &[u8; 32], bit: usize) -> bool {
+    (index[bit / 8] >> (bit % 8)) & 1 == 1
 }
 
-fn hash_two_to_one(left: Fr, right: Fr) -> Fr {
-    let mut hasher = Sha256::new();
-    hasher.update(left.into_bigint().to_bytes_le());
-    hasher.update(right.into_bigint().to_bytes_le());
-    let result = hasher.finalize();
-    Fr::from_le_bytes_mod_order(&result[..])
+fn flip_bit(index: &[u8; 32], bit: usize) -> [u8; 32] {
+    let mut flipped = *index;
+    flipped[bit / 8] ^= 1 << (bit % 8);
+    flipped
 }
 
-pub fn insert_to_tree(tree: &mut SparseMerkleTree, key: &str, value: &str) {
-    tree.data.insert(key.to_string(), value.to_string());
-    tree.compute_root();
+/// Leaf index reordered so that byte order matches numeric order, which
+/// makes every subtree a contiguous range; applying it twice is a no-op.
+fn sort_key(index: &[u8; 32]) -> [u8; 32] {
+    let mut key = *index;
+    key.reverse();
+    key
 }
 
-pub fn delete_from_tree(tree: &mut SparseMerkleTree, key: &str) {
-    tree.data.remove(key);
-    tree.compute_root();
+/// `sort_key`s of the leaves under the node at `height` above `index`.
+fn subtree_range(index: &[u8; 32], height: usize) -> RangeInclusive<[u8; 32]> {
+    let first = node_key(index, height).1;
+    let mut last = first;
+    let (bytes, bits) = (height / 8, height % 8);
+    last[..bytes].fill(0xff);
+    if bits > 0 {
+        last[bytes] |= (1u8 << bits) - 1;
+    }
+    sort_key(&first)..=sort_key(&last)
+}
+
+fn node_key(index: &[u8; 32], height: usize) -> NodeKey {
+    let mut prefix = *index;
+    let (bytes, bits) = (height / 8, height % 8);
+    prefix[..bytes].fill(0);
+    if bits > 0 {
+        prefix[bytes] &= !((1u8 << bits) - 1);
+    }
+    (height, prefix)
 }
 
-pub struct PathElement {
-    pub value: Vec<u8>,
+/// `key` and `value` may be given in borrowed form, e.g. `&str` for a
+/// `String` key.
+pub fn insert_to_tree<F, H, K, V, Q, R>(tree: &mut SparseMerkleTree<F, H, K, V>, key: &Q, value: &R)
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Clone,
+    Q: TreeEncoding + ToOwned<Owned = K> + ?Sized,
+    R: TreeEncoding + ToOwned<Owned = V> + ?Sized,
+{
+    tree.begin_version();
+    tree.set_data(key.to_owned(), Some(value.to_owned()));
+    let index = key_index(&tree.hasher, key);
+    let leaf = hash_leaf(&tree.hasher, key, value);
+    tree.set_leaf(&index, Some(leaf));
+    tree.rehash(BTreeSet::from([index]));
+}
+
+pub fn delete_from_tree<F, H, K, V, Q>(tree: &mut SparseMerkleTree<F, H, K, V>, key: &Q)
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Clone + Borrow<Q>,
+    Q: TreeEncoding + ToOwned<Owned = K> + Ord + ?Sized,
+{
+    if tree.data.contains_key(key) {
+        tree.begin_version();
+        tree.set_data(key.to_owned(), None);
+        let index = key_index(&tree.hasher, key);
+        tree.set_leaf(&index, None);
+        tree.rehash(BTreeSet::from([index]));
+    }
+}
+
+/// One sibling on the leaf-to-root path; `is_left` is set when the sibling is
+/// the left child, i.e. the path goes through the right branch at that height.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathElement<F: PrimeField = Fr> {
+    pub value: F,
     pub is_left: bool,
 }
 
-pub struct MerkleTreePath {
-    pub path: Vec<PathElement>,
+/// Exactly `TREE_DEPTH` siblings, ordered from the leaf up to the root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTreePath<F: PrimeField = Fr> {
+    pub path: Vec<PathElement<F>>,
 }
 
-pub fn get_merkle_path(tree: &SparseMerkleTree, key: &str) -> MerkleTreePath {
-    let mut path = vec![];
-    
-    // Sprawdź czy klucz istnieje w drzewie
+/// Membership path for `key`; fails with [`Error::KeyNotFound`] when `key`
+/// is not in the tree (see [`get_non_membership_proof`] for absent keys).
+pub fn get_merkle_path<F, H, K, V, Q>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    key: &Q,
+) -> Result<MerkleTreePath<F>, Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Borrow<Q>,
+    Q: TreeEncoding + Ord + ?Sized,
+{
     if !tree.data.contains_key(key) {
-        return MerkleTreePath { path };
+        return Err(Error::KeyNotFound);
     }
-    
-    // Dla uproszczenia: generuj proof jako wszystkie inne pary w drzewie
-    // W prawdziwej implementacji SMT byłaby to ścieżka przez drzewo
-    for (k, v) in &tree.data {
-        if k != key {
-            let other_key_hash = hash_string(k);
-            let other_value_hash = hash_string(v);
-            let combined_hash = hash_two_to_one(other_key_hash, other_value_hash);
-            
-            path.push(PathElement {
-                value: combined_hash.into_bigint().to_bytes_le(),
-                is_left: true, // W prawdziwej implementacji zależałoby od pozycji w drzewie
-            });
+
+    Ok(tree.sibling_path(&key_index(&tree.hasher, key)))
+}
+
+/// Folds `leaf` up through the siblings in `path`, rejecting paths whose
+/// length or left/right bits do not match the leaf `index`.
+fn root_from_path<F: PrimeField, H: TreeHasher<F>>(
+    hasher: &H,
+    index: &[u8; 32],
+    leaf: F,
+    path: &MerkleTreePath<F>,
+) -> Result<F, Error> {
+    if path.path.len() != TREE_DEPTH {
+        return Err(Error::WrongPathLength { expected: TREE_DEPTH, actual: path.path.len() });
+    }
+
+    let mut current = leaf;
+    for (height, element) in path.path.iter().enumerate() {
+        if element.is_left != index_bit(index, height) {
+            return Err(Error::PathDirectionMismatch { height });
         }
+        let sibling = element.value;
+        current = if element.is_left {
+            hasher.hash_two_to_one(sibling, current)
+        } else {
+            hasher.hash_two_to_one(current, sibling)
+        };
     }
-    MerkleTreePath { path }
+    Ok(current)
+}
+
+/// Checks that `(key, value)` is a leaf of the SHA-256 tree with the given `root`.
+pub fn verify_membership<Q, R>(
+    root: Fr,
+    key: &Q,
+    value: &R,
+    path: &MerkleTreePath,
+) -> Result<(), Error>
+where
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    verify_membership_with(&Sha256Hasher, root, key, value, path)
+}
+
+pub fn verify_membership_with<F, H, Q, R>(
+    hasher: &H,
+    root: F,
+    key: &Q,
+    value: &R,
+    path: &MerkleTreePath<F>,
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    let leaf = hash_leaf(hasher, key, value);
+    expect_root(root_from_path(hasher, &key_index(hasher, key), leaf, path)?, root)
+}
+
+/// Shows that the leaf slot a key maps to is empty under some root. The slot
+/// index is the key's own hash, so an occupied slot can only ever hold that
+/// key; an empty slot therefore proves the key is absent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonMembershipProof<F: PrimeField = Fr> {
+    pub path: MerkleTreePath<F>,
+}
+
+/// Returns `None` when `key` is present in the tree.
+pub fn get_non_membership_proof<F, H, K, V, Q>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    key: &Q,
+) -> Option<NonMembershipProof<F>>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Borrow<Q>,
+    Q: TreeEncoding + Ord + ?Sized,
+{
+    if tree.data.contains_key(key) {
+        return None;
+    }
+
+    Some(NonMembershipProof {
+        path: tree.sibling_path(&key_index(&tree.hasher, key)),
+    })
+}
+
+/// Checks that `key` has no leaf in the SHA-256 tree with the given `root`.
+pub fn verify_non_membership<Q: TreeEncoding + ?Sized>(
+    root: Fr,
+    key: &Q,
+    proof: &NonMembershipProof,
+) -> Result<(), Error> {
+    verify_non_membership_with(&Sha256Hasher, root, key, proof)
+}
+
+pub fn verify_non_membership_with<F, H, Q>(
+    hasher: &H,
+    root: F,
+    key: &Q,
+    proof: &NonMembershipProof<F>,
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    Q: TreeEncoding + ?Sized,
+{
+    // Puste liście mają wartość default_hashes[0], czyli zero
+    expect_root(root_from_path(hasher, &key_index(hasher, key), F::zero(), &proof.path)?, root)
+}
+
+pub fn verify_proof(proof_bytes: Vec<u8>, inputs: (&str, u64, &[u8])) -> Result<(), Error> {
+    verify_proof_with::<Fr, _>(&Sha256Hasher, proof_bytes, inputs)
 }
 
-pub fn verify_proof(proof_bytes: Vec<u8>, inputs: (&str, u64, &[u8])) -> bool {
+pub fn verify_proof_with<F: PrimeField, H: TreeHasher<F>>(
+    hasher: &H,
+    proof_bytes: Vec<u8>,
+    inputs: (&str, u64, &[u8]),
+) -> Result<(), Error> {
     let (name, id, expected_root_bytes) = inputs;
     
-    // Deserializuj proof (w uproszczeniu - proof to lista hashów)
-    if proof_bytes.len() % 32 != 0 {
-        return false; // Nieprawidłowy format proof'a
+    // Proof to 256 siblingów (little-endian), od liścia do korzenia
+    let element_len = field_byte_len::<F>();
+    if proof_bytes.len() != TREE_DEPTH * element_len {
+        return Err(Error::BadEncoding(format!(
+            "expected {} proof bytes, got {}",
+            TREE_DEPTH * element_len,
+            proof_bytes.len()
+        )));
     }
-    
-    // Oblicz hash pary (name, id)
-    let name_hash = hash_string(name);
-    let id_hash = hash_string(&id.to_string());
-    let mut current_hash = hash_two_to_one(name_hash, id_hash);
-    
-    // Przetworz każdy element proof'a
-    let num_proof_elements = proof_bytes.len() / 32;
-    for i in 0..num_proof_elements {
-        let start = i * 32;
-        let end = start + 32;
-        let proof_element = &proof_bytes[start..end];
-        
-        let proof_hash = Fr::from_le_bytes_mod_order(proof_element);
-        current_hash = hash_two_to_one(current_hash, proof_hash);
+
+    let index = key_index(hasher, name);
+    let path = MerkleTreePath {
+        path: proof_bytes
+            .chunks(element_len)
+            .enumerate()
+            .map(|(height, chunk)| PathElement {
+                value: field_from_bytes(chunk),
+                is_left: index_bit(&index, height),
+            })
+            .collect(),
+    };
+
+    let expected_root = field_from_bytes(expected_root_bytes);
+    verify_membership_with(hasher, expected_root, name, &id, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Root over `leaves` (sorted by index) computed from scratch, splitting
+    /// on one index bit per level.
+    fn reference_node(tree: &SparseMerkleTree, leaves: &[([u8; 32], Fr)], height: usize) -> Fr {
+        match leaves {
+            [] => tree.default_hashes[height],
+            [(_, leaf)] if height == 0 => *leaf,
+            _ => {
+                let (right, left): (Vec<_>, Vec<_>) =
+                    leaves.iter().partition(|(index, _)| index_bit(index, height - 1));
+                tree.hasher.hash_two_to_one(
+                    reference_node(tree, &left, height - 1),
+                    reference_node(tree, &right, height - 1),
+                )
+            }
+        }
     }
-    
-    // Porównaj z oczekiwanym rootem
-    let expected_root = Fr::from_le_bytes_mod_order(expected_root_bytes);
-    current_hash == expected_root
-}
\ No newline at end of file
+
+    fn reference_root(tree: &SparseMerkleTree) -> Fr {
+        let leaves: Vec<_> = tree
+            .data
+            .iter()
+            .map(|(key, value)| {
+                let index = key_index::<Fr, _, _>(&tree.hasher, key.as_str());
+                (index, hash_leaf(&tree.hasher, key.as_str(), value.as_str()))
+            })
+            .collect();
+        reference_node(tree, &leaves, TREE_DEPTH)
+    }
+
+    #[test]
+    fn stores_a_linear_number_of_nodes_and_keeps_the_root() {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0..500 {
+            insert_to_tree(&mut tree, format!("user{}", i).as_str(), "1");
+        }
+        assert_eq!(tree.root(), reference_root(&tree));
+        assert!(tree.node_count() < 4 * tree.data.len(), "{} nodes", tree.node_count());
+
+        for i in (0..500).step_by(3) {
+            delete_from_tree(&mut tree, format!("user{}", i).as_str());
+        }
+        assert_eq!(tree.root(), reference_root(&tree));
+        assert!(tree.node_count() < 4 * tree.data.len(), "{} nodes", tree.node_count());
+    }
+
+    #[test]
+    fn deleting_every_key_leaves_no_nodes() {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0..50 {
+            insert_to_tree(&mut tree, format!("user{}", i).as_str(), "1");
+        }
+        for i in 0..50 {
+            delete_from_tree(&mut tree, format!("user{}", i).as_str());
+        }
+        assert_eq!(tree.root(), tree.default_hashes[TREE_DEPTH]);
+        assert_eq!(tree.node_count(), 0);
+    }
+
+    #[test]
+    fn paths_verify_after_deletes() {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0..100 {
+            insert_to_tree(&mut tree, format!("user{}", i).as_str(), "7");
+        }
+        for i in (0..100).step_by(2) {
+            delete_from_tree(&mut tree, format!("user{}", i).as_str());
+        }
+        for i in 0..100 {
+            let key = format!("user{}", i);
+            if i % 2 == 1 {
+                let path = get_merkle_path(&tree, key.as_str()).unwrap();
+                verify_membership(tree.root(), key.as_str(), "7", &path).unwrap();
+            } else {
+                assert_eq!(get_merkle_path(&tree, key.as_str()), Err(Error::KeyNotFound));
+                let proof = get_non_membership_proof(&tree, key.as_str()).unwrap();
+                verify_non_membership(tree.root(), key.as_str(), &proof).unwrap();
+            }
+        }
+    }
+}
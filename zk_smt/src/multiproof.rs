@@ -0,0 +1,215 @@
+// One proof for many keys against the same root. Siblings shared by several
+// paths are sent once, siblings that are computed from other requested leaves
+// are not sent at all, and default siblings are left out as in the
+// compressed single-key proof.
+
+use ark_bls12_381::Fr;
+use ark_ff::PrimeField;
+use ark_std::vec::Vec;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    flip_bit, hash_leaf, index_bit, key_index, node_key, Sha256Hasher, SparseMerkleTree,
+    expect_root, Error, TreeEncoding, TreeHasher, TREE_DEPTH,
+};
+
+/// A stored node, addressed like the tree's own node map: `index` is a leaf
+/// index with the low `height` bits cleared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiProofNode<F: PrimeField = Fr> {
+    pub height: usize,
+    pub index: [u8; 32],
+    pub value: F,
+}
+
+/// Non-default siblings needed to recompute the root from a set of leaves,
+/// ordered by height and then index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiProof<F: PrimeField = Fr> {
+    pub nodes: Vec<MultiProofNode<F>>,
+}
+
+/// Builds a proof covering every key in `keys`, present or absent.
+pub fn get_multi_proof<F, H, K, V, Q>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    keys: &[&Q],
+) -> MultiProof<F>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord,
+    Q: TreeEncoding + ?Sized,
+{
+    let mut level: BTreeSet<[u8; 32]> =
+        keys.iter().map(|key| key_index(&tree.hasher, *key)).collect();
+    let mut nodes = Vec::new();
+
+    for height in 0..TREE_DEPTH {
+        let mut parents = BTreeSet::new();
+        for index in &level {
+            let sibling = flip_bit(index, height);
+            if !level.contains(&sibling) {
+                let value = tree.node(height, &sibling);
+                if value != tree.default_hashes[height] {
+                    nodes.push(MultiProofNode { height, index: sibling, value });
+                }
+            }
+            parents.insert(node_key(index, height + 1).1);
+        }
+        level = parents;
+    }
+
+    MultiProof { nodes }
+}
+
+/// Checks every `(key, value)` pair against the SHA-256 tree with the given
+/// `root`; a `None` value claims the key is absent.
+pub fn verify_multi_proof<Q, R>(
+    root: Fr,
+    entries: &[(&Q, Option<&R>)],
+    proof: &MultiProof,
+) -> Result<(), Error>
+where
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    verify_multi_proof_with(&Sha256Hasher, root, entries, proof)
+}
+
+pub fn verify_multi_proof_with<F, H, Q, R>(
+    hasher: &H,
+    root: F,
+    entries: &[(&Q, Option<&R>)],
+    proof: &MultiProof<F>,
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    if entries.is_empty() {
+        return Err(Error::MalformedProof("no entries to check"));
+    }
+
+    let mut siblings = BTreeMap::new();
+    for node in &proof.nodes {
+        if node.height >= TREE_DEPTH {
+            return Err(Error::MalformedProof("node height out of range"));
+        }
+        if siblings.insert(node_key(&node.index, node.height), node.value).is_some() {
+            return Err(Error::MalformedProof("duplicate node"));
+        }
+    }
+
+    // Puste liście mają wartość zero, tak jak w verify_non_membership_with
+    let mut level = BTreeMap::new();
+    for &(key, value) in entries {
+        let leaf = value.map_or(F::zero(), |value| hash_leaf(hasher, key, value));
+        if level.insert(key_index(hasher, key), leaf).is_some_and(|other| other != leaf) {
+            return Err(Error::MalformedProof("conflicting values for the same key"));
+        }
+    }
+
+    let mut default = F::zero();
+    for height in 0..TREE_DEPTH {
+        let mut parents = BTreeMap::new();
+        for (index, value) in &level {
+            let sibling_index = flip_bit(index, height);
+            let sibling = match level.get(&sibling_index) {
+                // Rodzic policzony już przy lewym dziecku
+                Some(_) if index_bit(index, height) => continue,
+                Some(sibling) => *sibling,
+                None => siblings
+                    .get(&(height, sibling_index))
+                    .copied()
+                    .unwrap_or(default),
+            };
+            let parent = if index_bit(index, height) {
+                hasher.hash_two_to_one(sibling, *value)
+            } else {
+                hasher.hash_two_to_one(*value, sibling)
+            };
+            parents.insert(node_key(index, height + 1).1, parent);
+        }
+        level = parents;
+        default = hasher.hash_two_to_one(default, default);
+    }
+
+    let computed = level.into_values().next().expect("the last level holds only the root");
+    expect_root(computed, root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tree_with_keys;
+
+    #[test]
+    fn verifies_present_and_absent_keys() {
+        let tree = tree_with_keys(40);
+        let proof = get_multi_proof(&tree, &["user3", "user17", "nobody", "user39"]);
+        let entries = [
+            ("user3", Some("3")),
+            ("user17", Some("17")),
+            ("nobody", None),
+            ("user39", Some("39")),
+        ];
+        assert_eq!(verify_multi_proof(tree.root(), &entries, &proof), Ok(()));
+
+        // Only absent keys, in an empty tree as well
+        let proof = get_multi_proof(&tree, &["nobody", "someone"]);
+        let entries: [(&str, Option<&str>); 2] = [("nobody", None), ("someone", None)];
+        assert_eq!(verify_multi_proof(tree.root(), &entries, &proof), Ok(()));
+        let empty = SparseMerkleTree::new();
+        let proof = get_multi_proof(&empty, &["nobody"]);
+        assert!(proof.nodes.is_empty());
+        assert_eq!(verify_multi_proof(empty.root(), &entries[..1], &proof), Ok(()));
+    }
+
+    #[test]
+    fn rejects_tampered_proofs() {
+        let tree = tree_with_keys(40);
+        let proof = get_multi_proof(&tree, &["user3", "nobody"]);
+        let entries = [("user3", Some("3")), ("nobody", None)];
+        assert_eq!(verify_multi_proof(tree.root(), &entries, &proof), Ok(()));
+
+        let wrong_value = [("user3", Some("4")), ("nobody", None)];
+        assert_eq!(
+            verify_multi_proof(tree.root(), &wrong_value, &proof),
+            Err(Error::RootMismatch)
+        );
+        let claimed_absent: [(&str, Option<&str>); 2] = [("user3", None), ("nobody", None)];
+        assert_eq!(
+            verify_multi_proof(tree.root(), &claimed_absent, &proof),
+            Err(Error::RootMismatch)
+        );
+
+        let mut tampered = proof.clone();
+        tampered.nodes[0].value += Fr::from(1u64);
+        assert_eq!(
+            verify_multi_proof(tree.root(), &entries, &tampered),
+            Err(Error::RootMismatch)
+        );
+
+        let mut missing = proof.clone();
+        missing.nodes.pop();
+        assert_eq!(
+            verify_multi_proof(tree.root(), &entries, &missing),
+            Err(Error::RootMismatch)
+        );
+
+        let mut duplicated = proof.clone();
+        duplicated.nodes.push(proof.nodes[0].clone());
+        assert!(matches!(
+            verify_multi_proof(tree.root(), &entries, &duplicated),
+            Err(Error::MalformedProof(_))
+        ));
+
+        let conflicting = [("user3", Some("3")), ("user3", Some("4"))];
+        assert!(matches!(
+            verify_multi_proof(tree.root(), &conflicting, &proof),
+            Err(Error::MalformedProof(_))
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+// Poseidon permutation with parameters derived the same way as the reference
+// implementation (generate_parameters_grain.sage), which is also where
+// circomlib's round constants and MDS matrices come from.
+
+use ark_ff::{BigInteger, PrimeField};
+use ark_std::vec::Vec;
+
+/// circomlib's partial round counts for state widths 2 through 17
+/// (`N_ROUNDS_P` in poseidon.circom); every width uses 8 full rounds.
+const CIRCOM_PARTIAL_ROUNDS: [usize; 16] = [56, 57, 56, 60, 60, 63, 64, 63, 60, 66, 60, 65, 70, 60, 64, 68];
+const CIRCOM_FULL_ROUNDS: usize = 8;
+const CIRCOM_ALPHA: u64 = 5;
+
+/// Round constants and MDS matrix for one state width.
+#[derive(Clone)]
+pub struct PoseidonParameters<F: PrimeField> {
+    pub ark: Vec<F>,
+    pub mds: Vec<Vec<F>>,
+    pub full_rounds: usize,
+    pub partial_rounds: usize,
+    pub width: usize,
+    pub alpha: u64,
+}
+
+impl<F: PrimeField> PoseidonParameters<F> {
+    /// Generates parameters for a prime field with an `x^alpha` S-box.
+    ///
+    /// The MDS matrix is the first Cauchy matrix produced by the Grain
+    /// stream; the reference script additionally re-samples matrices that
+    /// fail its invariant-subspace checks, which never happens for the
+    /// widths circomlib ships.
+    pub fn generate(width: usize, full_rounds: usize, partial_rounds: usize, alpha: u64) -> Self {
+        let mut grain = Grain::new(
+            F::MODULUS_BIT_SIZE as u16,
+            width as u16,
+            full_rounds as u16,
+            partial_rounds as u16,
+        );
+
+        let ark = (0..(full_rounds + partial_rounds) * width)
+            .map(|_| grain.next_field_element::<F>(true))
+            .collect();
+
+        let points: Vec<F> = (0..2 * width)
+            .map(|_| grain.next_field_element::<F>(false))
+            .collect();
+        let (xs, ys) = points.split_at(width);
+        let mds = xs
+            .iter()
+            .map(|x| {
+                ys.iter()
+                    .map(|y| (*x + y).inverse().expect("Cauchy points must not sum to zero"))
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            ark,
+            mds,
+            full_rounds,
+            partial_rounds,
+            width,
+            alpha,
+        }
+    }
+
+    /// Parameters of circomlib's `Poseidon(n_inputs)` template. Over BN254
+    /// these reproduce `poseidon_constants.circom` exactly; over other fields
+    /// they are the same construction with field-specific constants.
+    pub fn circom(n_inputs: usize) -> Self {
+        assert!(
+            (1..=CIRCOM_PARTIAL_ROUNDS.len()).contains(&n_inputs),
+            "circomlib Poseidon supports 1 to 16 inputs"
+        );
+        let width = n_inputs + 1;
+        Self::generate(width, CIRCOM_FULL_ROUNDS, CIRCOM_PARTIAL_ROUNDS[width - 2], CIRCOM_ALPHA)
+    }
+}
+
+#[derive(Clone)]
+pub struct Poseidon<F: PrimeField> {
+    params: PoseidonParameters<F>,
+}
+
+impl<F: PrimeField> Poseidon<F> {
+    pub fn new(params: PoseidonParameters<F>) -> Self {
+        Self { params }
+    }
+
+    pub fn circom(n_inputs: usize) -> Self {
+        Self::new(PoseidonParameters::circom(n_inputs))
+    }
+
+    pub fn params(&self) -> &PoseidonParameters<F> {
+        &self.params
+    }
+
+    /// Hashes exactly `width - 1` inputs. The state starts as
+    /// `[0, inputs..]` and the first element is returned, as in circomlib.
+    pub fn hash(&self, inputs: &[F]) -> F {
+        let p = &self.params;
+        assert_eq!(inputs.len(), p.width - 1, "Poseidon input count must be width - 1");
+
+        let mut state = Vec::with_capacity(p.width);
+        state.push(F::zero());
+        state.extend_from_slice(inputs);
+
+        let half_full = p.full_rounds / 2;
+        for round in 0..p.full_rounds + p.partial_rounds {
+            for (i, s) in state.iter_mut().enumerate() {
+                *s += p.ark[round * p.width + i];
+            }
+
+            if round < half_full || round >= half_full + p.partial_rounds {
+                for s in state.iter_mut() {
+                    *s = s.pow([p.alpha]);
+                }
+            } else {
+                state[0] = state[0].pow([p.alpha]);
+            }
+
+            state = p
+                .mds
+                .iter()
+                .map(|row| row.iter().zip(&state).map(|(m, s)| *m * s).sum())
+                .collect();
+        }
+
+        state[0]
+    }
+}
+
+/// The 80-bit Grain LFSR used by the Poseidon reference script to derive
+/// round constants and MDS matrices.
+struct Grain {
+    state: [bool; 80],
+}
+
+impl Grain {
+    fn new(field_size: u16, width: u16, full_rounds: u16, partial_rounds: u16) -> Self {
+        let mut bits = Vec::with_capacity(80);
+        let mut push = |value: u64, len: usize| {
+            for i in (0..len).rev() {
+                bits.push((value >> i) & 1 == 1);
+            }
+        };
+        push(1, 2); // prime field
+        push(0, 4); // x^alpha S-box
+        push(field_size as u64, 12);
+        push(width as u64, 12);
+        push(full_rounds as u64, 10);
+        push(partial_rounds as u64, 10);
+        push((1 << 30) - 1, 30);
+
+        let mut state = [false; 80];
+        state.copy_from_slice(&bits);
+        let mut grain = Self { state };
+        for _ in 0..160 {
+            grain.clock();
+        }
+        grain
+    }
+
+    fn clock(&mut self) -> bool {
+        let s = &self.state;
+        let bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+        self.state.copy_within(1.., 0);
+        self.state[79] = bit;
+        bit
+    }
+
+    // Bits come in pairs; the second is kept only when the first is set.
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.clock();
+            let bit = self.clock();
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    /// Reads `MODULUS_BIT_SIZE` bits, big-endian. With `reject` set,
+    /// out-of-range values are discarded, otherwise they are reduced.
+    fn next_field_element<F: PrimeField>(&mut self, reject: bool) -> F {
+        loop {
+            let bits: Vec<bool> = (0..F::MODULUS_BIT_SIZE).map(|_| self.next_bit()).collect();
+            let value = F::BigInt::from_bits_be(&bits);
+            match F::from_bigint(value) {
+                Some(element) => return element,
+                None if !reject => return F::from_be_bytes_mod_order(&value.to_bytes_be()),
+                None => continue,
+            }
+        }
+    }
+}
+
+/// Drop-in equivalent of circomlib's `Poseidon(n)` over BN254, the curve the
+/// snarkjs circuits are compiled for.
+pub mod bn254 {
+    use ark_bn254::Fr;
+
+    use super::Poseidon;
+
+    /// Hashes `inputs` like `Poseidon(inputs.len())` in Circom, e.g.
+    /// `hash(&[1, 2])` is
+    /// `7853200120776062878684798364095072458815029376092732009249414926327459813530`.
+    /// Parameters are derived on every call; keep a [`Poseidon`] from
+    /// [`Poseidon::circom`] around when hashing repeatedly.
+    pub fn hash(inputs: &[Fr]) -> Fr {
+        Poseidon::circom(inputs.len()).hash(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+    use std::str::FromStr;
+
+    use super::bn254::hash;
+
+    // Known answers of circomlib's Poseidon(n) over BN254
+    fn check(inputs: &[u64], expected: &str) {
+        let inputs: Vec<Fr> = inputs.iter().map(|x| Fr::from(*x)).collect();
+        assert_eq!(hash(&inputs), Fr::from_str(expected).unwrap());
+    }
+
+    #[test]
+    fn matches_circomlib_with_one_input() {
+        check(
+            &[1],
+            "18586133768512220936620570745912940619677854269274689475585506675881198879027",
+        );
+    }
+
+    #[test]
+    fn matches_circomlib_with_two_inputs() {
+        check(
+            &[1, 2],
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+        );
+    }
+
+    #[test]
+    fn matches_circomlib_with_four_inputs() {
+        check(
+            &[1, 2, 3, 4],
+            "18821383157269793795438455681495246036402687001665670618754263018637548127333",
+        );
+    }
+}
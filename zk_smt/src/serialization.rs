@@ -0,0 +1,900 @@
+// Binary encoding of trees and proofs, built on ark-serialize so field
+// elements use the same representation as the rest of arkworks. Integers are
+// little-endian, lengths are u64, and a field element `F` is its compressed
+// ark-serialize form (`field_byte_len::<F>()` little-endian bytes). A root is
+// just such an `F`.
+//
+// SparseMerkleTree:
+//
+//   u8          format version (TREE_FORMAT_VERSION)
+//   map         data: count, then (key, value) pairs in key order
+//   F           root
+//   map         stored inner nodes: count, then ((height: u64, index: [u8; 32]), F)
+//   u64         tree version (number of writes so far)
+//   u64         history limit
+//   u64         number of snapshots, then per snapshot, oldest first:
+//                 u64 version, F root, map of replaced nodes (as above, with
+//                 an optional F), map of replaced entries (key, optional value)
+//
+// Keys and values are stored as their `TreeEncoding` bytes: a u64 length,
+// then the bytes. For `String` this is ark-serialize's own String layout.
+// The hasher is not stored; deserializing rebuilds it with `H::default()`.
+// Leaves are not stored either and are rehashed from the entries.
+//
+// MerkleTreePath (NonMembershipProof is encoded as its path):
+//
+//   u8          format version (PATH_FORMAT_VERSION)
+//   u64         number of siblings, always TREE_DEPTH
+//   per sibling F value, then u8 is_left (0 or 1), leaf to root
+//
+// CompressedMerkleTreePath:
+//
+//   u8          format version (COMPRESSED_PATH_FORMAT_VERSION)
+//   [u8; 32]    bitmap of stored siblings, bit h = height h
+//   u64         number of siblings, equal to the bitmap's popcount
+//   per sibling F, leaf to root
+//
+// MultiProof:
+//
+//   u8          format version (MULTI_PROOF_FORMAT_VERSION)
+//   u64         number of nodes
+//   per node    u64 height (< TREE_DEPTH), [u8; 32] index, F value
+//
+// An optional value is a u8 flag, then the value if the flag is 1.
+//
+// UpdateProof (keys and values encoded as in the tree):
+//
+//   u8          format version (UPDATE_PROOF_FORMAT_VERSION)
+//   bytes       key
+//   Option      old value, then new value
+//   F           old root, then new root
+//   ...         path as a CompressedMerkleTreePath, with its own version byte
+//
+// Readers reject versions they do not know, so a layout change must bump the
+// matching constant.
+
+use ark_ff::PrimeField;
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, Read, SerializationError, Valid, Validate,
+    Write,
+};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+use crate::{
+    flip_bit, hash_leaf, key_index, node_key, sort_key, CompressedMerkleTreePath, MerkleTreePath,
+    MultiProof, MultiProofNode, NonMembershipProof, PathElement, RootSnapshot, SparseMerkleTree,
+    TreeDecoding, TreeEncoding, TreeHasher, UpdateProof, TREE_DEPTH,
+};
+
+pub const TREE_FORMAT_VERSION: u8 = 1;
+pub const PATH_FORMAT_VERSION: u8 = 1;
+pub const COMPRESSED_PATH_FORMAT_VERSION: u8 = 1;
+pub const MULTI_PROOF_FORMAT_VERSION: u8 = 1;
+pub const UPDATE_PROOF_FORMAT_VERSION: u8 = 1;
+
+/// A key or value written as its `TreeEncoding` bytes.
+struct Encoded<'a, T: ?Sized>(&'a T);
+
+impl<T: TreeEncoding + ?Sized> CanonicalSerialize for Encoded<'_, T> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        self.0.encode().serialize_with_mode(writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        self.0.encode().serialized_size(compress)
+    }
+}
+
+/// Reads back what [`Encoded`] wrote.
+struct Decoded<T>(T);
+
+impl<T: Sync> Valid for Decoded<T> {
+    fn check(&self) -> Result<(), SerializationError> {
+        Ok(())
+    }
+}
+
+impl<T: TreeDecoding + Sync> CanonicalDeserialize for Decoded<T> {
+    fn deserialize_with_mode<R: Read>(
+        reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let bytes = Vec::<u8>::deserialize_with_mode(reader, compress, validate)?;
+        T::decode(&bytes).map(Decoded).ok_or(SerializationError::InvalidData)
+    }
+}
+
+fn serialize_entries<'a, K, V, I, W>(
+    entries: I,
+    mut writer: W,
+    compress: Compress,
+) -> Result<(), SerializationError>
+where
+    K: TreeEncoding + 'a,
+    V: CanonicalSerialize,
+    I: ExactSizeIterator<Item = (&'a K, V)>,
+    W: Write,
+{
+    (entries.len() as u64).serialize_with_mode(&mut writer, compress)?;
+    for (key, value) in entries {
+        Encoded(key).serialize_with_mode(&mut writer, compress)?;
+        value.serialize_with_mode(&mut writer, compress)?;
+    }
+    Ok(())
+}
+
+fn entries_size<'a, K, V, I>(entries: I, compress: Compress) -> usize
+where
+    K: TreeEncoding + 'a,
+    V: CanonicalSerialize,
+    I: ExactSizeIterator<Item = (&'a K, V)>,
+{
+    let len = (entries.len() as u64).serialized_size(compress);
+    len + entries
+        .map(|(key, value)| {
+            Encoded(key).serialized_size(compress) + value.serialized_size(compress)
+        })
+        .sum::<usize>()
+}
+
+fn deserialize_entries<K, V, R>(
+    mut reader: R,
+    compress: Compress,
+    validate: Validate,
+) -> Result<BTreeMap<K, V>, SerializationError>
+where
+    K: TreeDecoding + Ord + Sync,
+    V: CanonicalDeserialize,
+    R: Read,
+{
+    let len = u64::deserialize_with_mode(&mut reader, compress, validate)?;
+    let mut entries = BTreeMap::new();
+    for _ in 0..len {
+        let Decoded(key) = Decoded::<K>::deserialize_with_mode(&mut reader, compress, validate)?;
+        entries.insert(key, V::deserialize_with_mode(&mut reader, compress, validate)?);
+    }
+    Ok(entries)
+}
+
+impl<F, H, K, V> CanonicalSerialize for SparseMerkleTree<F, H, K, V>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: TreeEncoding + Ord,
+    V: TreeEncoding,
+{
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        TREE_FORMAT_VERSION.serialize_with_mode(&mut writer, compress)?;
+        serialize_entries(self.data.iter().map(|(k, v)| (k, Encoded(v))), &mut writer, compress)?;
+        self.root.serialize_with_mode(&mut writer, compress)?;
+        self.nodes.serialize_with_mode(&mut writer, compress)?;
+        self.version.serialize_with_mode(&mut writer, compress)?;
+        self.history_limit.serialize_with_mode(&mut writer, compress)?;
+        // Same layout as a Vec: a u64 count, then the snapshots
+        (self.history.len() as u64).serialize_with_mode(&mut writer, compress)?;
+        for snapshot in &self.history {
+            snapshot.serialize_with_mode(&mut writer, compress)?;
+        }
+        Ok(())
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        TREE_FORMAT_VERSION.serialized_size(compress)
+            + entries_size(self.data.iter().map(|(k, v)| (k, Encoded(v))), compress)
+            + self.root.serialized_size(compress)
+            + self.nodes.serialized_size(compress)
+            + self.version.serialized_size(compress)
+            + self.history_limit.serialized_size(compress)
+            + (self.history.len() as u64).serialized_size(compress)
+            + self
+                .history
+                .iter()
+                .map(|snapshot| snapshot.serialized_size(compress))
+                .sum::<usize>()
+    }
+}
+
+impl<F, H, K, V> Valid for SparseMerkleTree<F, H, K, V>
+where
+    F: PrimeField,
+    H: TreeHasher<F> + Sync,
+    K: TreeEncoding + Ord + Sync,
+    V: TreeEncoding + Sync,
+{
+    /// Checks that no two entries share a leaf, that every stored node is
+    /// the hash of its two children and is stored only where the tree
+    /// would store it, that the root is the hash of the two children
+    /// directly below it, and that the history is a run of consecutive
+    /// versions ending just before the current one. A child inside a
+    /// single-key subtree is recomputed from its leaf, so this costs about
+    /// one path of hashes per entry.
+    fn check(&self) -> Result<(), SerializationError> {
+        let consecutive = self
+            .history
+            .iter()
+            .rev()
+            .zip((0..self.version).rev())
+            .all(|(snapshot, version)| snapshot.version == version);
+        if self.history.len() as u64 > self.version || !consecutive {
+            return Err(SerializationError::InvalidData);
+        }
+
+        // Leaves come from the entries, so only distinct indices can fail
+        if self.leaves.len() != self.data.len() {
+            return Err(SerializationError::InvalidData);
+        }
+
+        for (&(height, prefix), value) in &self.nodes {
+            let canonical = (1..TREE_DEPTH).contains(&height)
+                && node_key(&prefix, height) == (height, prefix)
+                && *value != self.default_hashes[height]
+                && self.leaf_count(height + 1, &prefix) > 1;
+            if !canonical {
+                return Err(SerializationError::InvalidData);
+            }
+            let left = self.node(height - 1, &prefix);
+            let right = self.node(height - 1, &flip_bit(&prefix, height - 1));
+            if self.hasher.hash_two_to_one(left, right) != *value {
+                return Err(SerializationError::InvalidData);
+            }
+        }
+
+        let left = self.node(TREE_DEPTH - 1, &[0u8; 32]);
+        let mut right_index = [0u8; 32];
+        right_index[31] = 0x80;
+        let right = self.node(TREE_DEPTH - 1, &right_index);
+        if self.hasher.hash_two_to_one(left, right) != self.root {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+impl<F, H, K, V> CanonicalDeserialize for SparseMerkleTree<F, H, K, V>
+where
+    F: PrimeField,
+    H: TreeHasher<F> + Default + Sync,
+    K: TreeDecoding + Ord + Sync,
+    V: TreeDecoding + Sync,
+{
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let format = u8::deserialize_with_mode(&mut reader, compress, validate)?;
+        if format != TREE_FORMAT_VERSION {
+            return Err(SerializationError::InvalidData);
+        }
+
+        let mut tree = Self::with_hasher(H::default());
+        tree.data = deserialize_entries::<_, Decoded<V>, _>(&mut reader, compress, validate)?
+            .into_iter()
+            .map(|(key, Decoded(value))| (key, value))
+            .collect();
+        tree.root = F::deserialize_with_mode(&mut reader, compress, validate)?;
+        tree.nodes = BTreeMap::deserialize_with_mode(&mut reader, compress, validate)?;
+        tree.version = u64::deserialize_with_mode(&mut reader, compress, validate)?;
+        tree.history_limit = usize::deserialize_with_mode(&mut reader, compress, validate)?;
+        let len = u64::deserialize_with_mode(&mut reader, compress, validate)?;
+        for _ in 0..len {
+            tree.history.push_back(RootSnapshot::deserialize_with_mode(
+                &mut reader,
+                compress,
+                validate,
+            )?);
+        }
+
+        tree.leaves = tree
+            .data
+            .iter()
+            .map(|(key, value)| {
+                (sort_key(&key_index(&tree.hasher, key)), hash_leaf(&tree.hasher, key, value))
+            })
+            .collect();
+        for snapshot in &mut tree.history {
+            snapshot.leaves = snapshot
+                .data
+                .iter()
+                .map(|(key, value)| {
+                    let leaf = value.as_ref().map(|value| hash_leaf(&tree.hasher, key, value));
+                    (sort_key(&key_index(&tree.hasher, key)), leaf)
+                })
+                .collect();
+        }
+        if validate == Validate::Yes {
+            tree.check()?;
+        }
+        Ok(tree)
+    }
+}
+
+impl<F: PrimeField, K: TreeEncoding, V: TreeEncoding> CanonicalSerialize for RootSnapshot<F, K, V> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        self.version.serialize_with_mode(&mut writer, compress)?;
+        self.root.serialize_with_mode(&mut writer, compress)?;
+        self.nodes.serialize_with_mode(&mut writer, compress)?;
+        serialize_entries(self.replaced_entries(), writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        self.version.serialized_size(compress)
+            + self.root.serialized_size(compress)
+            + self.nodes.serialized_size(compress)
+            + entries_size(self.replaced_entries(), compress)
+    }
+}
+
+impl<F: PrimeField, K, V> RootSnapshot<F, K, V> {
+    fn replaced_entries(&self) -> impl ExactSizeIterator<Item = (&K, Option<Encoded<'_, V>>)> {
+        self.data.iter().map(|(key, value)| (key, value.as_ref().map(Encoded)))
+    }
+}
+
+impl<F: PrimeField, K: Sync, V: Sync> Valid for RootSnapshot<F, K, V> {
+    fn check(&self) -> Result<(), SerializationError> {
+        Ok(())
+    }
+}
+
+impl<F, K, V> CanonicalDeserialize for RootSnapshot<F, K, V>
+where
+    F: PrimeField,
+    K: TreeDecoding + Ord + Sync,
+    V: TreeDecoding + Sync,
+{
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let version = u64::deserialize_with_mode(&mut reader, compress, validate)?;
+        let root = F::deserialize_with_mode(&mut reader, compress, validate)?;
+        let nodes = BTreeMap::deserialize_with_mode(&mut reader, compress, validate)?;
+        let data = deserialize_entries::<_, Option<Decoded<V>>, _>(reader, compress, validate)?
+            .into_iter()
+            .map(|(key, value)| (key, value.map(|Decoded(value)| value)))
+            .collect();
+        // Leaves are left empty for the tree to rebuild from `data`
+        Ok(Self { version, root, leaves: BTreeMap::new(), nodes, data })
+    }
+}
+
+impl<F: PrimeField> CanonicalSerialize for PathElement<F> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        self.value.serialize_with_mode(&mut writer, compress)?;
+        self.is_left.serialize_with_mode(&mut writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        self.value.serialized_size(compress) + self.is_left.serialized_size(compress)
+    }
+}
+
+impl<F: PrimeField> Valid for PathElement<F> {
+    fn check(&self) -> Result<(), SerializationError> {
+        self.value.check()
+    }
+}
+
+impl<F: PrimeField> CanonicalDeserialize for PathElement<F> {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        Ok(Self {
+            value: F::deserialize_with_mode(&mut reader, compress, validate)?,
+            is_left: bool::deserialize_with_mode(&mut reader, compress, validate)?,
+        })
+    }
+}
+
+impl<F: PrimeField> CanonicalSerialize for MerkleTreePath<F> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        PATH_FORMAT_VERSION.serialize_with_mode(&mut writer, compress)?;
+        self.path.serialize_with_mode(&mut writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        PATH_FORMAT_VERSION.serialized_size(compress) + self.path.serialized_size(compress)
+    }
+}
+
+impl<F: PrimeField> Valid for MerkleTreePath<F> {
+    /// One sibling per level; absent keys fail before a path is built.
+    fn check(&self) -> Result<(), SerializationError> {
+        if self.path.len() != TREE_DEPTH {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+impl<F: PrimeField> CanonicalDeserialize for MerkleTreePath<F> {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let version = u8::deserialize_with_mode(&mut reader, compress, validate)?;
+        if version != PATH_FORMAT_VERSION {
+            return Err(SerializationError::InvalidData);
+        }
+
+        let path = Self {
+            path: Vec::deserialize_with_mode(&mut reader, compress, validate)?,
+        };
+        if validate == Validate::Yes {
+            path.check()?;
+        }
+        Ok(path)
+    }
+}
+
+impl<F: PrimeField> CanonicalSerialize for NonMembershipProof<F> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        self.path.serialize_with_mode(writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        self.path.serialized_size(compress)
+    }
+}
+
+impl<F: PrimeField> Valid for NonMembershipProof<F> {
+    fn check(&self) -> Result<(), SerializationError> {
+        self.path.check()
+    }
+}
+
+impl<F: PrimeField> CanonicalDeserialize for NonMembershipProof<F> {
+    fn deserialize_with_mode<R: Read>(
+        reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        Ok(Self {
+            path: MerkleTreePath::deserialize_with_mode(reader, compress, validate)?,
+        })
+    }
+}
+
+impl<F: PrimeField> CanonicalSerialize for CompressedMerkleTreePath<F> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        COMPRESSED_PATH_FORMAT_VERSION.serialize_with_mode(&mut writer, compress)?;
+        self.bitmap.serialize_with_mode(&mut writer, compress)?;
+        self.siblings.serialize_with_mode(&mut writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        COMPRESSED_PATH_FORMAT_VERSION.serialized_size(compress)
+            + self.bitmap.serialized_size(compress)
+            + self.siblings.serialized_size(compress)
+    }
+}
+
+impl<F: PrimeField> Valid for CompressedMerkleTreePath<F> {
+    fn check(&self) -> Result<(), SerializationError> {
+        if self.siblings.len() != self.stored_count() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+impl<F: PrimeField> CanonicalDeserialize for CompressedMerkleTreePath<F> {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let version = u8::deserialize_with_mode(&mut reader, compress, validate)?;
+        if version != COMPRESSED_PATH_FORMAT_VERSION {
+            return Err(SerializationError::InvalidData);
+        }
+
+        let path = Self {
+            bitmap: <[u8; 32]>::deserialize_with_mode(&mut reader, compress, validate)?,
+            siblings: Vec::deserialize_with_mode(&mut reader, compress, validate)?,
+        };
+        if validate == Validate::Yes {
+            path.check()?;
+        }
+        Ok(path)
+    }
+}
+
+impl<F: PrimeField> CanonicalSerialize for MultiProofNode<F> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        self.height.serialize_with_mode(&mut writer, compress)?;
+        self.index.serialize_with_mode(&mut writer, compress)?;
+        self.value.serialize_with_mode(&mut writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        self.height.serialized_size(compress)
+            + self.index.serialized_size(compress)
+            + self.value.serialized_size(compress)
+    }
+}
+
+impl<F: PrimeField> Valid for MultiProofNode<F> {
+    fn check(&self) -> Result<(), SerializationError> {
+        if self.height >= TREE_DEPTH {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+impl<F: PrimeField> CanonicalDeserialize for MultiProofNode<F> {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let node = Self {
+            height: usize::deserialize_with_mode(&mut reader, compress, validate)?,
+            index: <[u8; 32]>::deserialize_with_mode(&mut reader, compress, validate)?,
+            value: F::deserialize_with_mode(&mut reader, compress, validate)?,
+        };
+        if validate == Validate::Yes {
+            node.check()?;
+        }
+        Ok(node)
+    }
+}
+
+impl<F: PrimeField> CanonicalSerialize for MultiProof<F> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        MULTI_PROOF_FORMAT_VERSION.serialize_with_mode(&mut writer, compress)?;
+        self.nodes.serialize_with_mode(&mut writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        MULTI_PROOF_FORMAT_VERSION.serialized_size(compress) + self.nodes.serialized_size(compress)
+    }
+}
+
+impl<F: PrimeField> Valid for MultiProof<F> {
+    fn check(&self) -> Result<(), SerializationError> {
+        self.nodes.iter().try_for_each(Valid::check)
+    }
+}
+
+impl<F: PrimeField> CanonicalDeserialize for MultiProof<F> {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let version = u8::deserialize_with_mode(&mut reader, compress, validate)?;
+        if version != MULTI_PROOF_FORMAT_VERSION {
+            return Err(SerializationError::InvalidData);
+        }
+
+        Ok(Self {
+            nodes: Vec::deserialize_with_mode(&mut reader, compress, validate)?,
+        })
+    }
+}
+
+impl<F: PrimeField, K: TreeEncoding, V: TreeEncoding> CanonicalSerialize for UpdateProof<F, K, V> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        UPDATE_PROOF_FORMAT_VERSION.serialize_with_mode(&mut writer, compress)?;
+        Encoded(&self.key).serialize_with_mode(&mut writer, compress)?;
+        self.old_value.as_ref().map(Encoded).serialize_with_mode(&mut writer, compress)?;
+        self.new_value.as_ref().map(Encoded).serialize_with_mode(&mut writer, compress)?;
+        self.old_root.serialize_with_mode(&mut writer, compress)?;
+        self.new_root.serialize_with_mode(&mut writer, compress)?;
+        self.path.serialize_with_mode(&mut writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        UPDATE_PROOF_FORMAT_VERSION.serialized_size(compress)
+            + Encoded(&self.key).serialized_size(compress)
+            + self.old_value.as_ref().map(Encoded).serialized_size(compress)
+            + self.new_value.as_ref().map(Encoded).serialized_size(compress)
+            + self.old_root.serialized_size(compress)
+            + self.new_root.serialized_size(compress)
+            + self.path.serialized_size(compress)
+    }
+}
+
+impl<F: PrimeField, K: Sync, V: Sync> Valid for UpdateProof<F, K, V> {
+    fn check(&self) -> Result<(), SerializationError> {
+        self.path.check()
+    }
+}
+
+impl<F, K, V> CanonicalDeserialize for UpdateProof<F, K, V>
+where
+    F: PrimeField,
+    K: TreeDecoding + Sync,
+    V: TreeDecoding + Sync,
+{
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let version = u8::deserialize_with_mode(&mut reader, compress, validate)?;
+        if version != UPDATE_PROOF_FORMAT_VERSION {
+            return Err(SerializationError::InvalidData);
+        }
+
+        Ok(Self {
+            key: Decoded::deserialize_with_mode(&mut reader, compress, validate)?.0,
+            old_value: Option::<Decoded<V>>::deserialize_with_mode(&mut reader, compress, validate)?
+                .map(|Decoded(value)| value),
+            new_value: Option::<Decoded<V>>::deserialize_with_mode(&mut reader, compress, validate)?
+                .map(|Decoded(value)| value),
+            old_root: F::deserialize_with_mode(&mut reader, compress, validate)?,
+            new_root: F::deserialize_with_mode(&mut reader, compress, validate)?,
+            path: CompressedMerkleTreePath::deserialize_with_mode(&mut reader, compress, validate)?,
+        })
+    }
+}
+
+/// Canonical bytes of any of the types above (or a bare root).
+pub fn to_bytes<T: CanonicalSerialize>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(value.compressed_size());
+    value
+        .serialize_compressed(&mut bytes)
+        .expect("writing to a Vec cannot fail");
+    bytes
+}
+
+/// Inverse of [`to_bytes`]; the input must be consumed exactly.
+pub fn from_bytes<T: CanonicalDeserialize>(mut bytes: &[u8]) -> Result<T, SerializationError> {
+    let value = T::deserialize_compressed(&mut bytes)?;
+    if !bytes.is_empty() {
+        return Err(SerializationError::InvalidData);
+    }
+    Ok(value)
+}
+
+/// [`from_bytes`] without `Valid::check`, for bytes the caller wrote itself.
+/// A tree loads without recomputing its stored nodes, which matters when
+/// the check would not fit in one message, e.g. in `post_upgrade`.
+pub fn from_trusted_bytes<T: CanonicalDeserialize>(
+    mut bytes: &[u8],
+) -> Result<T, SerializationError> {
+    let value = T::deserialize_with_mode(&mut bytes, Compress::Yes, Validate::No)?;
+    if !bytes.is_empty() {
+        return Err(SerializationError::InvalidData);
+    }
+    Ok(value)
+}
+
+// serde goes through the canonical bytes: a hex string in human-readable
+// formats such as JSON, raw bytes otherwise.
+fn serialize_canonical<T: CanonicalSerialize, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let bytes = to_bytes(value);
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&encode_hex(&bytes))
+    } else {
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+fn deserialize_canonical<'de, T: CanonicalDeserialize, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    let bytes = if deserializer.is_human_readable() {
+        let hex = String::deserialize(deserializer)?;
+        decode_hex(&hex).ok_or_else(|| D::Error::custom("invalid hex string"))?
+    } else {
+        Vec::<u8>::deserialize(deserializer)?
+    };
+    from_bytes(&bytes).map_err(|e| D::Error::custom(format!("invalid canonical encoding: {}", e)))
+}
+
+macro_rules! impl_serde_via_canonical {
+    ($ty:ident<$($param:ident),+> where $($bounds:tt)*) => {
+        impl<$($param),+> Serialize for $ty<$($param),+> where $($bounds)* {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_canonical(self, serializer)
+            }
+        }
+
+        impl<'de, $($param),+> Deserialize<'de> for $ty<$($param),+> where $($bounds)* {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_canonical(deserializer)
+            }
+        }
+    };
+}
+
+impl_serde_via_canonical!(SparseMerkleTree<F, H, K, V> where
+    F: PrimeField,
+    H: TreeHasher<F> + Default + Sync,
+    K: TreeDecoding + Ord + Sync,
+    V: TreeDecoding + Sync
+);
+impl_serde_via_canonical!(PathElement<F> where F: PrimeField);
+impl_serde_via_canonical!(MerkleTreePath<F> where F: PrimeField);
+impl_serde_via_canonical!(NonMembershipProof<F> where F: PrimeField);
+impl_serde_via_canonical!(CompressedMerkleTreePath<F> where F: PrimeField);
+impl_serde_via_canonical!(MultiProof<F> where F: PrimeField);
+impl_serde_via_canonical!(UpdateProof<F, K, V> where
+    F: PrimeField,
+    K: TreeDecoding + Sync,
+    V: TreeDecoding + Sync
+);
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        delete_from_tree, delete_with_proof, get_compressed_merkle_path, get_merkle_path,
+        get_merkle_proof_at, get_multi_proof, get_non_membership_proof, insert_to_tree,
+        insert_with_proof, Fr,
+    };
+    use crate::test_util::tree_with_keys;
+
+    fn reload(tree: &SparseMerkleTree) -> Result<SparseMerkleTree, SerializationError> {
+        let mut bytes = Vec::new();
+        tree.serialize_compressed(&mut bytes).unwrap();
+        SparseMerkleTree::deserialize_compressed(bytes.as_slice())
+    }
+
+    #[test]
+    fn rejects_a_tampered_node() {
+        let mut tree = tree_with_keys(20);
+        assert!(reload(&tree).is_ok());
+        let (key, value) = tree.nodes.iter().nth(7).map(|(k, v)| (*k, *v)).unwrap();
+        tree.nodes.insert(key, value + Fr::from(1u64));
+        assert!(reload(&tree).is_err());
+
+        // Trusted bytes skip the node check
+        let loaded: SparseMerkleTree = from_trusted_bytes(&to_bytes(&tree)).unwrap();
+        assert_eq!(loaded.root(), tree.root());
+    }
+
+    #[test]
+    fn rejects_nodes_the_tree_would_not_store() {
+        let mut tree = tree_with_keys(20);
+        let (height, prefix) = *tree.nodes.keys().next().unwrap();
+        tree.nodes.insert((height, flip_bit(&prefix, 200)), tree.default_hashes[height]);
+        assert!(reload(&tree).is_err());
+
+        // A correct hash below the top of a single-key subtree is still rejected
+        let mut tree = tree_with_keys(1);
+        let index = *tree.leaves.keys().next().unwrap();
+        let index = sort_key(&index);
+        tree.nodes.insert(node_key(&index, 3), tree.node(3, &index));
+        assert!(reload(&tree).is_err());
+    }
+
+    fn round_trip<T>(value: &T)
+    where
+        T: CanonicalSerialize + CanonicalDeserialize + PartialEq + std::fmt::Debug,
+    {
+        let bytes = to_bytes(value);
+        assert_eq!(&from_bytes::<T>(&bytes).unwrap(), value);
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(from_bytes::<T>(&trailing).is_err());
+    }
+
+    #[test]
+    fn tree_round_trips_with_history() {
+        let mut tree = tree_with_keys(30);
+        for i in (0..30).step_by(4) {
+            delete_from_tree(&mut tree, format!("user{}", i).as_str());
+        }
+        insert_to_tree(&mut tree, "user1", "2");
+
+        let bytes = to_bytes(&tree);
+        let loaded: SparseMerkleTree = from_bytes(&bytes).unwrap();
+        assert_eq!(to_bytes(&loaded), bytes);
+        assert_eq!(loaded.root(), tree.root());
+        assert_eq!(loaded.version(), tree.version());
+        assert_eq!(loaded.node_count(), tree.node_count());
+        for version in tree.version() - 10..=tree.version() {
+            assert_eq!(
+                get_merkle_proof_at(&loaded, "user1", version),
+                get_merkle_proof_at(&tree, "user1", version)
+            );
+        }
+    }
+
+    #[test]
+    fn proofs_round_trip() {
+        let mut tree = tree_with_keys(10);
+        round_trip(&get_merkle_path(&tree, "user3").unwrap());
+        round_trip(&get_compressed_merkle_path(&tree, "user3").unwrap());
+        round_trip(&get_non_membership_proof(&tree, "nobody").unwrap());
+        round_trip(&get_multi_proof(&tree, &["user1", "user7", "nobody"]));
+        round_trip(&insert_with_proof(&mut tree, "user3", "9"));
+        round_trip(&delete_with_proof(&mut tree, "user3"));
+
+        let path = get_merkle_path(&tree, "user4").unwrap();
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(serde_json::from_str::<MerkleTreePath>(&json).unwrap(), path);
+    }
+
+    #[test]
+    fn rejects_paths_without_a_sibling_per_level() {
+        let tree = tree_with_keys(3);
+        let mut path = get_merkle_path(&tree, "user1").unwrap();
+        path.path.pop();
+        assert!(from_bytes::<MerkleTreePath>(&to_bytes(&path)).is_err());
+        path.path.clear();
+        assert!(from_bytes::<MerkleTreePath>(&to_bytes(&path)).is_err());
+        assert!(from_bytes::<NonMembershipProof>(&to_bytes(&path)).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_format_versions() {
+        let mut bytes = to_bytes(&tree_with_keys(3));
+        assert!(from_bytes::<SparseMerkleTree>(&bytes).is_ok());
+        for version in [0, TREE_FORMAT_VERSION + 1, u8::MAX] {
+            bytes[0] = version;
+            assert!(from_bytes::<SparseMerkleTree>(&bytes).is_err());
+        }
+    }
+}
@@ -0,0 +1,389 @@
+// Conversion between snarkjs' JSON files (verification_key.json, proof.json,
+// public.json) and arkworks Groth16 types.
+//
+// snarkjs writes every coordinate as a decimal string and every point as a
+// projective triple: `[x, y, "1"]` for affine points and `["0", "1", "0"]`
+// for the point at infinity. G2 coordinates are `[c0, c1]` pairs. The
+// Solidity calldata export (and our frontend's `formattedProof`) reverse each
+// pair to `[c1, c0]`; use `SnarkjsProof::reverse_g2_coordinates` to undo that
+// before converting.
+
+use ark_bls12_381::Bls12_381;
+use ark_bn254::Bn254;
+use ark_ec::pairing::Pairing;
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ec::AffineRepr;
+use ark_ff::{Field, PrimeField};
+use ark_groth16::{Proof, VerifyingKey};
+use ark_std::vec::Vec;
+use ark_std::Zero;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// A pairing-friendly curve snarkjs can produce proofs for.
+pub trait SnarkjsCurve:
+    Pairing<G1Affine = Affine<Self::G1Config>, G2Affine = Affine<Self::G2Config>>
+{
+    type G1Config: SWCurveConfig;
+    type G2Config: SWCurveConfig;
+
+    /// The value of the `curve` field in snarkjs JSON.
+    const NAME: &'static str;
+}
+
+impl SnarkjsCurve for Bn254 {
+    type G1Config = ark_bn254::g1::Config;
+    type G2Config = ark_bn254::g2::Config;
+    const NAME: &'static str = "bn128";
+}
+
+impl SnarkjsCurve for Bls12_381 {
+    type G1Config = ark_bls12_381::g1::Config;
+    type G2Config = ark_bls12_381::g2::Config;
+    const NAME: &'static str = "bls12381";
+}
+
+/// `proof.json`. `protocol` and `curve` may be omitted on input.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SnarkjsProof {
+    pub pi_a: Vec<String>,
+    pub pi_b: Vec<Vec<String>>,
+    pub pi_c: Vec<String>,
+    #[serde(default)]
+    pub protocol: String,
+    #[serde(default)]
+    pub curve: String,
+}
+
+/// `verification_key.json`. `vk_alphabeta_12` is derived data and is not
+/// read back on import.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SnarkjsVerifyingKey {
+    pub protocol: String,
+    pub curve: String,
+    #[serde(rename = "nPublic")]
+    pub n_public: usize,
+    pub vk_alpha_1: Vec<String>,
+    pub vk_beta_2: Vec<Vec<String>>,
+    pub vk_gamma_2: Vec<Vec<String>>,
+    pub vk_delta_2: Vec<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vk_alphabeta_12: Vec<Vec<Vec<String>>>,
+    #[serde(rename = "IC")]
+    pub ic: Vec<Vec<String>>,
+}
+
+impl SnarkjsProof {
+    pub fn from_proof<E: SnarkjsCurve>(proof: &Proof<E>) -> Self {
+        Self {
+            pi_a: g1_to_json(&proof.a),
+            pi_b: g2_to_json(&proof.b),
+            pi_c: g1_to_json(&proof.c),
+            protocol: "groth16".to_string(),
+            curve: E::NAME.to_string(),
+        }
+    }
+
+    pub fn to_proof<E: SnarkjsCurve>(&self) -> Result<Proof<E>, Error> {
+        check_header::<E>(&self.protocol, &self.curve, true)?;
+        Ok(Proof {
+            a: g1_from_json(&self.pi_a)?,
+            b: g2_from_json(&self.pi_b)?,
+            c: g1_from_json(&self.pi_c)?,
+        })
+    }
+
+    /// Swaps every G2 coordinate pair between snarkjs order `[c0, c1]` and
+    /// the Solidity calldata order `[c1, c0]`.
+    pub fn reverse_g2_coordinates(&mut self) {
+        for coordinate in self.pi_b.iter_mut() {
+            coordinate.reverse();
+        }
+    }
+}
+
+impl SnarkjsVerifyingKey {
+    pub fn from_verifying_key<E: SnarkjsCurve>(vk: &VerifyingKey<E>) -> Self {
+        let alphabeta = E::pairing(vk.alpha_g1, vk.beta_g2).0;
+        let flat: Vec<String> = alphabeta
+            .to_base_prime_field_elements()
+            .map(|e| e.into_bigint().to_string())
+            .collect();
+        // GT is a degree-12 tower: two Fq6 halves of three Fq2 pairs each
+        let vk_alphabeta_12 = flat
+            .chunks(6)
+            .map(|half| half.chunks(2).map(|pair| pair.to_vec()).collect())
+            .collect();
+
+        Self {
+            protocol: "groth16".to_string(),
+            curve: E::NAME.to_string(),
+            n_public: vk.gamma_abc_g1.len().saturating_sub(1),
+            vk_alpha_1: g1_to_json(&vk.alpha_g1),
+            vk_beta_2: g2_to_json(&vk.beta_g2),
+            vk_gamma_2: g2_to_json(&vk.gamma_g2),
+            vk_delta_2: g2_to_json(&vk.delta_g2),
+            vk_alphabeta_12,
+            ic: vk.gamma_abc_g1.iter().map(g1_to_json).collect(),
+        }
+    }
+
+    pub fn to_verifying_key<E: SnarkjsCurve>(&self) -> Result<VerifyingKey<E>, Error> {
+        check_header::<E>(&self.protocol, &self.curve, false)?;
+        if self.ic.len() != self.n_public + 1 {
+            return Err(Error::BadEncoding(format!(
+                "IC has {} points but nPublic is {}",
+                self.ic.len(),
+                self.n_public
+            )));
+        }
+
+        Ok(VerifyingKey {
+            alpha_g1: g1_from_json(&self.vk_alpha_1)?,
+            beta_g2: g2_from_json(&self.vk_beta_2)?,
+            gamma_g2: g2_from_json(&self.vk_gamma_2)?,
+            delta_g2: g2_from_json(&self.vk_delta_2)?,
+            gamma_abc_g1: self.ic.iter().map(|p| g1_from_json(p)).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+pub fn parse_proof<E: SnarkjsCurve>(json: &str) -> Result<Proof<E>, Error> {
+    serde_json::from_str::<SnarkjsProof>(json)
+        .map_err(|e| Error::BadEncoding(format!("Invalid proof JSON: {}", e)))?
+        .to_proof()
+}
+
+pub fn proof_to_json<E: SnarkjsCurve>(proof: &Proof<E>) -> String {
+    to_json(&SnarkjsProof::from_proof(proof))
+}
+
+pub fn parse_verifying_key<E: SnarkjsCurve>(json: &str) -> Result<VerifyingKey<E>, Error> {
+    serde_json::from_str::<SnarkjsVerifyingKey>(json)
+        .map_err(|e| Error::BadEncoding(format!("Invalid verifying key JSON: {}", e)))?
+        .to_verifying_key()
+}
+
+pub fn verifying_key_to_json<E: SnarkjsCurve>(vk: &VerifyingKey<E>) -> String {
+    to_json(&SnarkjsVerifyingKey::from_verifying_key(vk))
+}
+
+/// Parses `public.json`, a list of decimal scalar field elements.
+pub fn parse_public_signals<F: PrimeField>(json: &str) -> Result<Vec<F>, Error> {
+    let signals: Vec<String> = serde_json::from_str(json)
+        .map_err(|e| Error::BadEncoding(format!("Invalid public signals JSON: {}", e)))?;
+    public_signals_from_strings(&signals)
+}
+
+pub fn public_signals_from_strings<F: PrimeField>(signals: &[String]) -> Result<Vec<F>, Error> {
+    signals.iter().map(|s| parse_field(s)).collect()
+}
+
+pub fn public_signals_to_json<F: PrimeField>(signals: &[F]) -> String {
+    let signals: Vec<String> = signals.iter().map(|s| s.into_bigint().to_string()).collect();
+    to_json(&signals)
+}
+
+// snarkjs pretty-prints with a single space of indentation
+fn to_json<T: Serialize>(value: &T) -> String {
+    let mut out = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+    value
+        .serialize(&mut serializer)
+        .expect("snarkjs JSON types always serialize");
+    String::from_utf8(out).expect("serde_json emits UTF-8")
+}
+
+fn check_header<E: SnarkjsCurve>(protocol: &str, curve: &str, optional: bool) -> Result<(), Error> {
+    if !(optional && protocol.is_empty()) && protocol != "groth16" {
+        return Err(Error::BadEncoding(format!("Unsupported protocol: {}", protocol)));
+    }
+    if !(optional && curve.is_empty()) && curve != E::NAME {
+        return Err(Error::BadEncoding(format!(
+            "Expected curve {}, got {}",
+            E::NAME,
+            curve
+        )));
+    }
+    Ok(())
+}
+
+/// Parses a decimal string, rejecting values outside the field.
+pub fn parse_field<F: PrimeField>(s: &str) -> Result<F, Error> {
+    let value = BigUint::parse_bytes(s.as_bytes(), 10)
+        .ok_or_else(|| Error::BadEncoding(format!("Invalid decimal field element: {}", s)))?;
+    F::BigInt::try_from(value)
+        .ok()
+        .and_then(F::from_bigint)
+        .ok_or_else(|| Error::BadEncoding(format!("Field element out of range: {}", s)))
+}
+
+fn coordinate_from_json<F: Field>(parts: &[String]) -> Result<F, Error> {
+    let elements = parts
+        .iter()
+        .map(|p| parse_field::<F::BasePrimeField>(p))
+        .collect::<Result<Vec<_>, _>>()?;
+    F::from_base_prime_field_elems(&elements).ok_or_else(|| {
+        Error::BadEncoding(format!(
+            "Expected {} base field elements per coordinate, got {}",
+            F::extension_degree(),
+            parts.len()
+        ))
+    })
+}
+
+fn coordinate_to_json<F: Field>(value: &F) -> Vec<String> {
+    value
+        .to_base_prime_field_elements()
+        .map(|e| e.into_bigint().to_string())
+        .collect()
+}
+
+/// Builds a point from `[x, y]` or a Jacobian triple `[x, y, z]`; snarkjs
+/// normally writes `z = 1`, but any non-zero `z` is normalised.
+fn point_from_coordinates<P: SWCurveConfig>(
+    coordinates: &[P::BaseField],
+) -> Result<Affine<P>, Error> {
+    let (x, y) = match coordinates {
+        [x, y] => (*x, *y),
+        [_, _, z] if z.is_zero() => return Ok(Affine::identity()),
+        [x, y, z] => {
+            let z_inv = z.inverse().expect("z is non-zero");
+            let z_inv2 = z_inv.square();
+            (*x * z_inv2, *y * z_inv2 * z_inv)
+        }
+        _ => {
+            return Err(Error::BadEncoding(format!(
+                "Point must have 2 or 3 coordinates, got {}",
+                coordinates.len()
+            )))
+        }
+    };
+
+    let point = Affine::new_unchecked(x, y);
+    if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(Error::BadEncoding(
+            "Point is not in the prime-order subgroup".to_string(),
+        ));
+    }
+    Ok(point)
+}
+
+fn point_to_coordinates<P: SWCurveConfig>(point: &Affine<P>) -> [P::BaseField; 3] {
+    match point.xy() {
+        Some((x, y)) => [*x, *y, P::BaseField::ONE],
+        None => [P::BaseField::ZERO, P::BaseField::ONE, P::BaseField::ZERO],
+    }
+}
+
+fn g1_from_json<P: SWCurveConfig>(coordinates: &[String]) -> Result<Affine<P>, Error> {
+    let parsed = coordinates
+        .iter()
+        .map(|c| coordinate_from_json::<P::BaseField>(std::slice::from_ref(c)))
+        .collect::<Result<Vec<_>, _>>()?;
+    point_from_coordinates(&parsed)
+}
+
+fn g2_from_json<P: SWCurveConfig>(coordinates: &[Vec<String>]) -> Result<Affine<P>, Error> {
+    let parsed = coordinates
+        .iter()
+        .map(|c| coordinate_from_json::<P::BaseField>(c))
+        .collect::<Result<Vec<_>, _>>()?;
+    point_from_coordinates(&parsed)
+}
+
+fn g1_to_json<P: SWCurveConfig>(point: &Affine<P>) -> Vec<String> {
+    point_to_coordinates(point)
+        .iter()
+        .flat_map(coordinate_to_json)
+        .collect()
+}
+
+fn g2_to_json<P: SWCurveConfig>(point: &Affine<P>) -> Vec<Vec<String>> {
+    point_to_coordinates(point)
+        .iter()
+        .map(coordinate_to_json)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_groth16::{prepare_verifying_key, Groth16};
+
+    use super::*;
+
+    const MEMBERSHIP_PROOF: &str = include_str!("../../circuits/zk_membership_proof.json");
+    const MEMBERSHIP_PUBLIC: &str = include_str!("../../circuits/zk_membership_public.json");
+    const MEMBERSHIP_VKEY: &str = include_str!("../../circuits/zk_membership_vkey.json");
+    const MINIMAL_PROOF: &str = include_str!("../../circuits/proof.json");
+    const MINIMAL_PUBLIC: &str = include_str!("../../circuits/public.json");
+    const MINIMAL_VKEY: &str = include_str!("../../circuits/verification_key.json");
+
+    fn same_json(emitted: &str, fixture: &str) -> bool {
+        let parse = |json| serde_json::from_str::<serde_json::Value>(json).unwrap();
+        parse(emitted) == parse(fixture)
+    }
+
+    fn check_fixture(proof_json: &str, public_json: &str, vkey_json: &str) {
+        let proof = parse_proof::<Bn254>(proof_json).unwrap();
+        let mut inputs = parse_public_signals::<ark_bn254::Fr>(public_json).unwrap();
+        let vk = parse_verifying_key::<Bn254>(vkey_json).unwrap();
+        let pvk = prepare_verifying_key(&vk);
+        assert!(Groth16::<Bn254>::verify_proof(&pvk, &proof, &inputs).unwrap());
+
+        inputs[0] += ark_bn254::Fr::from(1u64);
+        assert!(!Groth16::<Bn254>::verify_proof(&pvk, &proof, &inputs).unwrap());
+
+        assert!(same_json(&proof_to_json(&proof), proof_json));
+        assert!(same_json(&verifying_key_to_json(&vk), vkey_json));
+        let inputs = parse_public_signals::<ark_bn254::Fr>(public_json).unwrap();
+        assert!(same_json(&public_signals_to_json(&inputs), public_json));
+    }
+
+    #[test]
+    fn membership_fixture_verifies_and_round_trips() {
+        check_fixture(MEMBERSHIP_PROOF, MEMBERSHIP_PUBLIC, MEMBERSHIP_VKEY);
+    }
+
+    #[test]
+    fn minimal_smt_fixture_verifies_and_round_trips() {
+        check_fixture(MINIMAL_PROOF, MINIMAL_PUBLIC, MINIMAL_VKEY);
+    }
+
+    #[test]
+    fn bls12_381_proof_and_key_round_trip() {
+        use ark_bls12_381::{Fr, G1Affine, G2Affine};
+        use ark_ec::CurveGroup;
+
+        let g1 = |k: u64| (G1Affine::generator() * Fr::from(k)).into_affine();
+        let g2 = |k: u64| (G2Affine::generator() * Fr::from(k)).into_affine();
+        let proof = Proof::<Bls12_381> { a: g1(2), b: g2(3), c: g1(5) };
+        let vk = VerifyingKey::<Bls12_381> {
+            alpha_g1: g1(7),
+            beta_g2: g2(11),
+            gamma_g2: g2(13),
+            delta_g2: g2(17),
+            gamma_abc_g1: vec![g1(19), g1(23)],
+        };
+
+        assert_eq!(parse_proof::<Bls12_381>(&proof_to_json(&proof)).unwrap(), proof);
+        let vk_json = verifying_key_to_json(&vk);
+        assert_eq!(parse_verifying_key::<Bls12_381>(&vk_json).unwrap(), vk);
+        assert!(parse_verifying_key::<Bn254>(&vk_json).is_err());
+    }
+
+    #[test]
+    fn rejects_the_wrong_curve() {
+        assert!(matches!(
+            parse_verifying_key::<Bls12_381>(MEMBERSHIP_VKEY),
+            Err(Error::BadEncoding(_))
+        ));
+        assert!(matches!(
+            parse_public_signals::<ark_bn254::Fr>(r#"["-1"]"#),
+            Err(Error::BadEncoding(_))
+        ));
+    }
+}
@@ -0,0 +1,12 @@
+// Fixtures shared by the unit tests of several modules.
+
+use crate::{insert_to_tree, SparseMerkleTree};
+
+/// A SHA-256 tree with keys `user0..user{n-1}`, each mapped to its number.
+pub(crate) fn tree_with_keys(n: usize) -> SparseMerkleTree {
+    let mut tree = SparseMerkleTree::new();
+    for i in 0..n {
+        insert_to_tree(&mut tree, format!("user{}", i).as_str(), format!("{}", i).as_str());
+    }
+    tree
+}
@@ -0,0 +1,227 @@
+// Proofs that a single write moved the tree from one root to another. A write
+// only rehashes the nodes on its own path, so the siblings are the same before
+// and after it and one path proves both roots.
+
+use ark_bls12_381::Fr;
+use ark_ff::PrimeField;
+use std::borrow::Borrow;
+
+use crate::compressed::root_from_compressed_path;
+use crate::{
+    delete_from_tree, hash_leaf, insert_to_tree, key_index, CompressedMerkleTreePath,
+    expect_root, Error, Sha256Hasher, SparseMerkleTree, TreeEncoding, TreeHasher,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpdateProof<F: PrimeField = Fr, K = String, V = String> {
+    pub key: K,
+    /// `None` when the key was absent before the write.
+    pub old_value: Option<V>,
+    /// `None` when the write deleted the key.
+    pub new_value: Option<V>,
+    pub old_root: F,
+    pub new_root: F,
+    pub path: CompressedMerkleTreePath<F>,
+}
+
+/// [`insert_to_tree`] that also returns the proof of the transition.
+pub fn insert_with_proof<F, H, K, V, Q, R>(
+    tree: &mut SparseMerkleTree<F, H, K, V>,
+    key: &Q,
+    value: &R,
+) -> UpdateProof<F, K, V>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Clone + Borrow<Q>,
+    V: Clone,
+    Q: TreeEncoding + ToOwned<Owned = K> + Ord + ?Sized,
+    R: TreeEncoding + ToOwned<Owned = V> + ?Sized,
+{
+    let (old_value, old_root, path) = state_before(tree, key);
+    insert_to_tree(tree, key, value);
+    UpdateProof {
+        key: key.to_owned(),
+        old_value,
+        new_value: Some(value.to_owned()),
+        old_root,
+        new_root: tree.root,
+        path,
+    }
+}
+
+/// [`delete_from_tree`] that also returns the proof of the transition.
+/// Deleting an absent key yields a proof with equal roots.
+pub fn delete_with_proof<F, H, K, V, Q>(
+    tree: &mut SparseMerkleTree<F, H, K, V>,
+    key: &Q,
+) -> UpdateProof<F, K, V>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Clone + Borrow<Q>,
+    V: Clone,
+    Q: TreeEncoding + ToOwned<Owned = K> + Ord + ?Sized,
+{
+    let (old_value, old_root, path) = state_before(tree, key);
+    delete_from_tree(tree, key);
+    UpdateProof {
+        key: key.to_owned(),
+        old_value,
+        new_value: None,
+        old_root,
+        new_root: tree.root,
+        path,
+    }
+}
+
+fn state_before<F, H, K, V, Q>(
+    tree: &SparseMerkleTree<F, H, K, V>,
+    key: &Q,
+) -> (Option<V>, F, CompressedMerkleTreePath<F>)
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: Ord + Borrow<Q>,
+    V: Clone,
+    Q: TreeEncoding + Ord + ?Sized,
+{
+    let path = tree.sibling_path(&key_index(&tree.hasher, key));
+    let path = CompressedMerkleTreePath::compress(&path, &tree.default_hashes)
+        .expect("sibling paths have one element per level");
+    (tree.data.get(key).cloned(), tree.root, path)
+}
+
+/// Checks `old_value` under `old_root` and returns the root after the key is
+/// set to `new_value` (`None` on either side means absent). Fails if the old
+/// state does not match.
+pub fn compute_updated_root_with<F, H, Q, R>(
+    hasher: &H,
+    old_root: F,
+    key: &Q,
+    old_value: Option<&R>,
+    new_value: Option<&R>,
+    path: &CompressedMerkleTreePath<F>,
+) -> Result<F, Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    Q: TreeEncoding + ?Sized,
+    R: TreeEncoding + ?Sized,
+{
+    let index = key_index(hasher, key);
+    let leaf = |value: Option<&R>| value.map_or(F::zero(), |value| hash_leaf(hasher, key, value));
+
+    expect_root(root_from_compressed_path(hasher, &index, leaf(old_value), path)?, old_root)?;
+    root_from_compressed_path(hasher, &index, leaf(new_value), path)
+}
+
+/// Checks a proof from the SHA-256 tree. Callers following a tree should also
+/// check that `proof.old_root` is the root they last trusted.
+pub fn verify_update_proof<K, V>(proof: &UpdateProof<Fr, K, V>) -> Result<(), Error>
+where
+    K: TreeEncoding,
+    V: TreeEncoding,
+{
+    verify_update_proof_with(&Sha256Hasher, proof)
+}
+
+pub fn verify_update_proof_with<F, H, K, V>(
+    hasher: &H,
+    proof: &UpdateProof<F, K, V>,
+) -> Result<(), Error>
+where
+    F: PrimeField,
+    H: TreeHasher<F>,
+    K: TreeEncoding,
+    V: TreeEncoding,
+{
+    let new_root = compute_updated_root_with(
+        hasher,
+        proof.old_root,
+        &proof.key,
+        proof.old_value.as_ref(),
+        proof.new_value.as_ref(),
+        &proof.path,
+    )?;
+    expect_root(new_root, proof.new_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Bn254PoseidonTree, PoseidonHasher};
+
+    #[test]
+    fn proofs_chain_through_inserts_updates_and_deletes() {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0..20 {
+            insert_to_tree(&mut tree, format!("user{}", i).as_str(), "1");
+        }
+
+        let mut root = tree.root();
+        let proofs = [
+            insert_with_proof(&mut tree, "alice", "1"),
+            insert_with_proof(&mut tree, "alice", "2"),
+            insert_with_proof(&mut tree, "user5", "3"),
+            delete_with_proof(&mut tree, "alice"),
+            delete_with_proof(&mut tree, "nobody"),
+        ];
+        for proof in &proofs {
+            assert_eq!(proof.old_root, root);
+            assert_eq!(verify_update_proof(proof), Ok(()));
+            root = proof.new_root;
+        }
+        assert_eq!(root, tree.root());
+
+        assert_eq!(proofs[0].old_value, None);
+        assert_eq!(proofs[1].old_value.as_deref(), Some("1"));
+        assert_eq!(proofs[3].new_value, None);
+        assert_eq!(proofs[4].old_root, proofs[4].new_root);
+    }
+
+    #[test]
+    fn rejects_tampered_proofs() {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0..20 {
+            insert_to_tree(&mut tree, format!("user{}", i).as_str(), "1");
+        }
+        let proof = insert_with_proof(&mut tree, "user3", "2");
+
+        let mut wrong_old = proof.clone();
+        wrong_old.old_value = Some("7".to_string());
+        assert_eq!(verify_update_proof(&wrong_old), Err(Error::RootMismatch));
+
+        let mut wrong_new = proof.clone();
+        wrong_new.new_value = Some("7".to_string());
+        assert_eq!(verify_update_proof(&wrong_new), Err(Error::RootMismatch));
+
+        let mut other_key = proof.clone();
+        other_key.key = "user4".to_string();
+        assert_eq!(verify_update_proof(&other_key), Err(Error::RootMismatch));
+
+        let mut other_path = proof;
+        other_path.path = insert_with_proof(&mut tree, "user4", "2").path;
+        assert_eq!(verify_update_proof(&other_path), Err(Error::RootMismatch));
+    }
+
+    #[test]
+    fn client_computes_the_new_root() {
+        let mut tree = Bn254PoseidonTree::with_hasher(PoseidonHasher::new());
+        for i in 0..10 {
+            insert_to_tree(&mut tree, format!("user{}", i).as_str(), "1");
+        }
+        let proof = insert_with_proof(&mut tree, "user2", "5");
+
+        let new_root = compute_updated_root_with(
+            tree.hasher(),
+            proof.old_root,
+            "user2",
+            Some("1"),
+            Some("5"),
+            &proof.path,
+        );
+        assert_eq!(new_root, Ok(tree.root()));
+        assert_eq!(verify_update_proof_with(tree.hasher(), &proof), Ok(()));
+    }
+}